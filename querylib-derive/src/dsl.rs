@@ -0,0 +1,114 @@
+use proc_macro2::{Ident, Span, TokenStream};
+use querylib_grammar::{Operand, Operator, Term, Token, TokenValue};
+use quote::quote;
+use syn::{Error, Expr, LitStr};
+
+// The grammar is the one querylib::query::parse reads, from querylib-grammar,
+// so q! only has to turn its tree into code. `{expr}` values and `cond(expr)`
+// clauses are the parts the runtime parser rejects.
+struct Generator<'a> {
+  lit: &'a LitStr,
+}
+
+impl<'a> Generator<'a> {
+  // Points at the offending text when the literal has no escapes and the
+  // compiler can split its span, otherwise at the whole literal.
+  fn error(&self, start: usize, end: usize, message: &str) -> Error {
+    let repr = self.lit.token().to_string();
+    let prefix = repr.find('"').map(|i| i + 1).unwrap_or(0);
+    let span = match repr[prefix..].starts_with(&self.lit.value()) {
+      true => self.lit.token().subspan(prefix + start..prefix + end),
+      false => None,
+    };
+    match span {
+      Some(span) => Error::new(span, message),
+      None => Error::new(self.lit.span(), format!("{} at offset {}", message, start)),
+    }
+  }
+
+  fn grammar_error(&self, error: querylib_grammar::Error) -> Error {
+    match &error {
+      querylib_grammar::Error::Invalid { start, end, .. } => self.error(*start, *end, &error.to_string()),
+      querylib_grammar::Error::Unexpected { token, .. } => self.error(token.start, token.end, &error.to_string()),
+      querylib_grammar::Error::UnexpectedEnd { .. } => Error::new(self.lit.span(), error),
+    }
+  }
+
+  fn expr(&self, token: &Token, code: &str) -> Result<Expr, Error> {
+    syn::parse_str::<Expr>(code).map_err(|e| self.error(token.start, token.end, &format!("invalid expression `{}`: {}", code, e)))
+  }
+
+  fn value(&self, token: &Token) -> Result<TokenStream, Error> {
+    Ok(match &token.value {
+      TokenValue::Number(n) => quote! { ::querylib::query::Value::Number(#n) },
+      TokenValue::Float(f) => quote! { ::querylib::query::Value::Float(#f) },
+      TokenValue::String(s) => quote! { ::querylib::query::Value::String(#s.to_owned()) },
+      TokenValue::Bool(b) => quote! { ::querylib::query::Value::Bool(#b) },
+      TokenValue::Interpolated(code) => {
+        let expr = self.expr(token, code)?;
+        quote! { ::querylib::query::Value::from(#expr) }
+      },
+      TokenValue::Array(values) => {
+        let values = values.iter().map(|v| self.value(v)).collect::<Result<Vec<TokenStream>, Error>>()?;
+        quote! { ::querylib::query::Value::Array(::std::boxed::Box::new(::std::vec![#(#values),*])) }
+      },
+      _ => return Err(self.error(token.start, token.end, &format!("expected a value, found {}", token.value))),
+    })
+  }
+
+  fn term(&self, term: &Term) -> Result<TokenStream, Error> {
+    match term {
+      Term::Group(group) => self.chain(group),
+      Term::Compare { field, operand, value } => {
+        let variant = match operand {
+          Operand::Eq => quote! { Eq },
+          Operand::Neq => quote! { Neq },
+          Operand::Gt => quote! { Gt },
+          Operand::GtE => quote! { GtE },
+          Operand::Lt => quote! { Lt },
+          Operand::LtE => quote! { LtE },
+          Operand::Rx => quote! { Rx },
+          Operand::In => quote! { In },
+          Operand::Contains => quote! { Contains },
+        };
+        let value = self.value(value)?;
+        Ok(quote! { ::querylib::query::Query::#variant { field: #field.to_owned(), value: #value } })
+      },
+    }
+  }
+
+  fn chain(&self, expr: &querylib_grammar::Expr) -> Result<TokenStream, Error> {
+    let mut query = self.term(expr.rest.last().map(|link| &link.term).unwrap_or(&expr.first))?;
+    for i in (0..expr.rest.len()).rev() {
+      let link = &expr.rest[i];
+      let left = self.term(if i == 0 { &expr.first } else { &expr.rest[i - 1].term })?;
+      let method = match link.operator {
+        Operator::And => quote! { and },
+        Operator::Or => quote! { or },
+      };
+      // Everything to the right of cond(...) is only built when it holds, the
+      // same as in the query! macro.
+      query = match &link.cond {
+        Some(token @ Token { value: TokenValue::Cond(code), .. }) => {
+          let condition = self.expr(token, code)?;
+          let lhs = Ident::new("lhs", Span::mixed_site());
+          quote! {{
+            let #lhs = #left;
+            if #condition { #lhs.#method(#query) } else { #lhs }
+          }}
+        },
+        _ => quote! { ::querylib::query::Query::#method(#left, #query) },
+      };
+    }
+    Ok(query)
+  }
+}
+
+pub fn expand(lit: &LitStr) -> Result<TokenStream, Error> {
+  let generator = Generator { lit };
+  let tokens = querylib_grammar::tokenize(&lit.value()).map_err(|e| generator.grammar_error(e))?;
+  match querylib_grammar::parse(&tokens).map_err(|e| generator.grammar_error(e))? {
+    Some(expr) => generator.chain(&expr),
+    None => Ok(quote! { ::querylib::query::Query::None }),
+  }
+}
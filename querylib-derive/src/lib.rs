@@ -0,0 +1,152 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, LitStr, PathArguments, Type};
+
+mod dsl;
+
+struct Field {
+  ident: syn::Ident,
+  name: String,
+  ty: Type,
+  nested: bool,
+}
+
+// `Option<T>` only says the field may be null; queries compare against `T`.
+fn inner(ty: &Type) -> &Type {
+  if let Type::Path(path) = ty {
+    if let Some(segment) = path.path.segments.last() {
+      if let PathArguments::AngleBracketed(args) = &segment.arguments {
+        if let (true, Some(GenericArgument::Type(ty))) = (segment.ident == "Option" && args.args.len() == 1, args.args.first()) {
+          return ty;
+        }
+      }
+    }
+  }
+  ty
+}
+
+// Query values hold integers as i64, which these do not fit in; usize is
+// 64 bits wide on most targets, so it goes with u64.
+fn too_wide(ty: &Type) -> bool {
+  if let Type::Path(path) = ty {
+    if let Some(segment) = path.path.segments.last() {
+      if let PathArguments::AngleBracketed(args) = &segment.arguments {
+        if let (true, Some(GenericArgument::Type(ty))) = (segment.ident == "Vec", args.args.first()) {
+          return too_wide(ty);
+        }
+      }
+      return ["u64", "usize", "u128", "i128"].iter().any(|name| segment.ident == name);
+    }
+  }
+  false
+}
+
+fn fields(input: &DeriveInput) -> Result<Vec<Field>, Error> {
+  let named = match &input.data {
+    Data::Struct(data) => match &data.fields {
+      Fields::Named(fields) => &fields.named,
+      _ => return Err(Error::new_spanned(&input.ident, "Queryable can only be derived for structs with named fields")),
+    },
+    _ => return Err(Error::new_spanned(&input.ident, "Queryable can only be derived for structs")),
+  };
+  let mut fields = vec![];
+  for field in named {
+    let ident = field.ident.clone().unwrap();
+    let mut name = ident.to_string().trim_start_matches("r#").to_owned();
+    let mut nested = false;
+    let mut skip = false;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("query")) {
+      attr.parse_nested_meta(|meta| {
+        if meta.path.is_ident("rename") {
+          name = meta.value()?.parse::<LitStr>()?.value();
+        } else if meta.path.is_ident("nested") {
+          nested = true;
+        } else if meta.path.is_ident("skip") {
+          skip = true;
+        } else {
+          return Err(meta.error("expected `rename = \"...\"`, `nested` or `skip`"));
+        }
+        Ok(())
+      })?;
+    }
+    if !skip && !nested && too_wide(inner(&field.ty)) {
+      return Err(Error::new_spanned(&field.ty, "u64, usize, u128 and i128 fields cannot be queried, use i64 or #[query(skip)]"));
+    }
+    if !skip {
+      fields.push(Field { ident, name, ty: inner(&field.ty).clone(), nested });
+    }
+  }
+  Ok(fields)
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+  if !input.generics.params.is_empty() {
+    return Err(Error::new_spanned(&input.generics, "Queryable cannot be derived for generic structs"));
+  }
+  let fields = fields(&input)?;
+  let vis = &input.vis;
+  let target = &input.ident;
+  let accessor = format_ident!("{}Fields", target);
+  let methods = fields.iter().map(|Field { ident, name, ty, nested }| match nested {
+    true => quote! {
+      pub fn #ident(&self) -> <#ty as ::querylib::query::Queryable>::Fields {
+        <#ty as ::querylib::query::Queryable>::fields(&format!("{}{}.", self.prefix, #name))
+      }
+    },
+    false => quote! {
+      pub fn #ident(&self) -> ::querylib::query::TypedField<#ty> {
+        ::querylib::query::TypedField::new(format!("{}{}", self.prefix, #name))
+      }
+    },
+  });
+  let shortcuts = fields.iter().map(|Field { ident, ty, nested, .. }| {
+    let output = match nested {
+      true => quote! { <#ty as ::querylib::query::Queryable>::Fields },
+      false => quote! { ::querylib::query::TypedField<#ty> },
+    };
+    quote! {
+      pub fn #ident() -> #output {
+        <#target as ::querylib::query::Queryable>::fields("").#ident()
+      }
+    }
+  });
+  Ok(quote! {
+    #vis struct #accessor {
+      prefix: String,
+    }
+
+    impl #accessor {
+      #(#methods)*
+    }
+
+    impl ::querylib::query::Queryable for #target {
+      type Fields = #accessor;
+      fn fields(prefix: &str) -> #accessor {
+        #accessor { prefix: prefix.to_owned() }
+      }
+    }
+
+    impl #target {
+      #(#shortcuts)*
+    }
+  })
+}
+
+#[proc_macro_derive(Queryable, attributes(query))]
+pub fn derive_queryable(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match expand(input) {
+    Ok(tokens) => tokens.into(),
+    Err(error) => error.to_compile_error().into(),
+  }
+}
+
+#[proc_macro]
+pub fn q(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as LitStr);
+  match dsl::expand(&input) {
+    Ok(tokens) => tokens.into(),
+    Err(error) => error.to_compile_error().into(),
+  }
+}
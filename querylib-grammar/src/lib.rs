@@ -0,0 +1,347 @@
+use std::fmt::{self, Write};
+
+// The filter syntax read by querylib::query::parse at runtime and by the q!
+// macro at compile time, kept here so the two cannot drift apart. `{expr}`
+// values and `cond(expr)` clauses only mean something to q!, the runtime
+// parser rejects them.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+  And,
+  Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+  Eq,
+  Neq,
+  Gt,
+  GtE,
+  Lt,
+  LtE,
+  Rx,
+  In,
+  Contains,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenValue {
+  Number(i64),
+  Float(f64),
+  String(String),
+  Bool(bool),
+
+  Array(Vec<Token>),
+
+  Operand(Operand),
+  Operator(Operator),
+  Identifier(String),
+  Open,
+  Close,
+
+  Interpolated(String),
+  Cond(String),
+}
+
+impl fmt::Display for TokenValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      TokenValue::Number(n) => write!(f, "{}", n),
+      TokenValue::String(s) => write!(f, "{:?}", s),
+      TokenValue::Bool(b) => write!(f, "{}", b),
+      TokenValue::Float(fv) => write!(f, "{:?}", fv),
+      TokenValue::Operand(o) => write!(f, ":{:?}", o),
+      TokenValue::Operator(o) => write!(f, ":{:?}", o),
+      TokenValue::Identifier(s) => write!(f, "{}", s),
+      TokenValue::Array(array) => {
+        f.write_char('[')?;
+        for (index, token) in array.iter().enumerate() {
+          if index > 0 {
+            f.write_str(", ")?;
+          }
+          write!(f, "{}", token.value)?;
+        }
+        f.write_char(']')
+      },
+      TokenValue::Open => f.write_char('('),
+      TokenValue::Close => f.write_char(')'),
+      TokenValue::Interpolated(code) => write!(f, "{{{}}}", code),
+      TokenValue::Cond(code) => write!(f, "cond({})", code),
+    }
+  }
+}
+
+// `start` and `end` are byte offsets into the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+  pub start: usize,
+  pub end: usize,
+  pub value: TokenValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  Invalid { start: usize, end: usize, message: &'static str },
+  Unexpected { token: Token, expected: &'static str },
+  UnexpectedEnd { expected: &'static str },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Invalid { message, .. } => f.write_str(message),
+      Error::Unexpected { token, expected } => write!(f, "expected {}, found {}", expected, token.value),
+      Error::UnexpectedEnd { expected } => write!(f, "expected {}, found the end of input", expected),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+struct Lexer<'a> {
+  source: &'a str,
+  chars: Vec<(usize, char)>,
+  index: usize,
+}
+
+impl<'a> Lexer<'a> {
+  fn peek(&self, offset: usize) -> Option<char> {
+    self.chars.get(self.index + offset).map(|(_, c)| *c)
+  }
+
+  fn position(&self) -> usize {
+    self.chars.get(self.index).map(|(p, _)| *p).unwrap_or(self.source.len())
+  }
+
+  fn invalid(&self, start: usize, message: &'static str) -> Error {
+    Error::Invalid { start, end: self.position(), message }
+  }
+
+  // Reads up to the bracket closing the one just consumed, for the Rust code
+  // inside `{...}` and `cond(...)`.
+  fn balanced(&mut self, start: usize, open: char, close: char, message: &'static str) -> Result<String, Error> {
+    let mut depth = 1;
+    let mut code = String::new();
+    while let Some(c) = self.peek(0) {
+      self.index += 1;
+      if c == open {
+        depth += 1;
+      } else if c == close {
+        depth -= 1;
+        if depth == 0 {
+          return Ok(code);
+        }
+      }
+      code.push(c);
+    }
+    Err(self.invalid(start, message))
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(c) = self.peek(0) {
+      if c.is_whitespace() || c == ',' {
+        self.index += 1;
+      } else {
+        break;
+      }
+    }
+  }
+
+  fn next(&mut self) -> Result<Option<Token>, Error> {
+    self.skip_whitespace();
+    let start = self.position();
+    let c = match self.peek(0) {
+      Some(c) => c,
+      None => return Ok(None),
+    };
+    self.index += 1;
+    let value = match (c, self.peek(0)) {
+      ('=', Some('=')) => { self.index += 1; TokenValue::Operand(Operand::Eq) },
+      ('!', Some('=')) => { self.index += 1; TokenValue::Operand(Operand::Neq) },
+      ('#', Some('=')) => { self.index += 1; TokenValue::Operand(Operand::Rx) },
+      ('>', Some('=')) => { self.index += 1; TokenValue::Operand(Operand::GtE) },
+      ('<', Some('=')) => { self.index += 1; TokenValue::Operand(Operand::LtE) },
+      ('&', Some('&')) => { self.index += 1; TokenValue::Operator(Operator::And) },
+      ('|', Some('|')) => { self.index += 1; TokenValue::Operator(Operator::Or) },
+      ('>', _) => TokenValue::Operand(Operand::Gt),
+      ('<', _) => TokenValue::Operand(Operand::Lt),
+      ('(', _) => TokenValue::Open,
+      (')', _) => TokenValue::Close,
+      ('{', _) => TokenValue::Interpolated(self.balanced(start, '{', '}', "unclosed `{`")?),
+      ('[', _) => {
+        let mut values = vec![];
+        loop {
+          self.skip_whitespace();
+          if self.peek(0) == Some(']') {
+            self.index += 1;
+            break;
+          }
+          match self.next()? {
+            Some(token) => match token.value {
+              TokenValue::Number(_) | TokenValue::Float(_) | TokenValue::String(_) | TokenValue::Bool(_)
+                | TokenValue::Interpolated(_) => values.push(token),
+              _ => return Err(Error::Unexpected { token, expected: "a value inside `[...]`" }),
+            },
+            None => return Err(self.invalid(start, "unclosed `[`")),
+          }
+        }
+        TokenValue::Array(values)
+      },
+      ('\'', _) => {
+        let mut string = String::new();
+        loop {
+          match self.peek(0) {
+            Some('\'') => { self.index += 1; break; },
+            Some(c) => { self.index += 1; string.push(c); },
+            None => return Err(self.invalid(start, "unterminated string")),
+          }
+        }
+        TokenValue::String(string)
+      },
+      // Digits and dots are read greedily, so `1.2.3` or a number too large
+      // for i64 only shows up as invalid here.
+      (c, _) if c.is_numeric() => {
+        let mut number = c.to_string();
+        while let Some(c) = self.peek(0).filter(|c| c.is_numeric() || *c == '.') {
+          self.index += 1;
+          number.push(c);
+        }
+        let value = match number.contains('.') {
+          true => number.parse().ok().map(TokenValue::Float),
+          false => number.parse().ok().map(TokenValue::Number),
+        };
+        value.ok_or_else(|| self.invalid(start, "invalid number"))?
+      },
+      (c, _) if c.is_alphabetic() || c == '_' => {
+        let mut word = c.to_string();
+        while let Some(c) = self.peek(0).filter(|c| c.is_alphanumeric() || *c == '.' || *c == '_') {
+          self.index += 1;
+          word.push(c);
+        }
+        match word.as_str() {
+          "in" => TokenValue::Operand(Operand::In),
+          "contains" => TokenValue::Operand(Operand::Contains),
+          "true" => TokenValue::Bool(true),
+          "false" => TokenValue::Bool(false),
+          "cond" if self.peek(0) == Some('(') => {
+            self.index += 1;
+            TokenValue::Cond(self.balanced(start, '(', ')', "unclosed `cond(`")?)
+          },
+          _ => TokenValue::Identifier(word),
+        }
+      },
+      _ => return Err(self.invalid(start, "unexpected character")),
+    };
+    Ok(Some(Token { start, end: self.position(), value }))
+  }
+}
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+  let mut lexer = Lexer { source, chars: source.char_indices().collect(), index: 0 };
+  let mut tokens = vec![];
+  while let Some(token) = lexer.next()? {
+    tokens.push(token);
+  }
+  Ok(tokens)
+}
+
+// `value` is one of the literal tokens, an identifier or an `{expr}`; what
+// each of those means is up to the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+  Compare { field: String, operand: Operand, value: Token },
+  Group(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+  pub operator: Operator,
+  pub cond: Option<Token>,
+  pub term: Term,
+}
+
+// Operators have no precedence and associate to the right, so `a && b || c`
+// is `a && (b || c)`. The links are kept in a flat list so that callers can
+// fold them from the back instead of recursing once per operator, which
+// keeps long generated filters off the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+  pub first: Term,
+  pub rest: Vec<Link>,
+}
+
+struct Parser<'a> {
+  tokens: &'a [Token],
+  index: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn peek(&self) -> Option<&'a Token> {
+    self.tokens.get(self.index)
+  }
+
+  fn next(&mut self, expected: &'static str) -> Result<&'a Token, Error> {
+    let token = self.peek().ok_or(Error::UnexpectedEnd { expected })?;
+    self.index += 1;
+    Ok(token)
+  }
+
+  fn term(&mut self) -> Result<Term, Error> {
+    let token = self.next("a field or `(`")?;
+    match &token.value {
+      TokenValue::Open => {
+        let expr = self.expr()?;
+        let close = self.next("`)`")?;
+        match close.value {
+          TokenValue::Close => Ok(Term::Group(Box::new(expr))),
+          _ => Err(Error::Unexpected { token: close.clone(), expected: "`)`" }),
+        }
+      },
+      TokenValue::Identifier(field) => {
+        let op = self.next("an operator")?;
+        let operand = match op.value {
+          TokenValue::Operand(operand) => operand,
+          _ => return Err(Error::Unexpected { token: op.clone(), expected: "an operator" }),
+        };
+        let value = self.next("a value")?;
+        match value.value {
+          TokenValue::Number(_) | TokenValue::Float(_) | TokenValue::String(_) | TokenValue::Bool(_) | TokenValue::Array(_)
+            | TokenValue::Identifier(_) | TokenValue::Interpolated(_) => {},
+          _ => return Err(Error::Unexpected { token: value.clone(), expected: "a value" }),
+        }
+        Ok(Term::Compare { field: field.clone(), operand, value: value.clone() })
+      },
+      _ => Err(Error::Unexpected { token: token.clone(), expected: "a field or `(`" }),
+    }
+  }
+
+  fn expr(&mut self) -> Result<Expr, Error> {
+    let first = self.term()?;
+    let mut rest = vec![];
+    while let Some(TokenValue::Operator(operator)) = self.peek().map(|t| &t.value) {
+      self.index += 1;
+      let cond = match self.peek() {
+        Some(token @ Token { value: TokenValue::Cond(_), .. }) => {
+          self.index += 1;
+          Some(token.clone())
+        },
+        _ => None,
+      };
+      rest.push(Link { operator: *operator, cond, term: self.term()? });
+    }
+    Ok(Expr { first, rest })
+  }
+}
+
+// An empty token list is the empty filter.
+pub fn parse(tokens: &[Token]) -> Result<Option<Expr>, Error> {
+  if tokens.is_empty() {
+    return Ok(None);
+  }
+  let mut parser = Parser { tokens, index: 0 };
+  let expr = parser.expr()?;
+  match parser.peek() {
+    None => Ok(Some(expr)),
+    Some(token) => Err(Error::Unexpected { token: token.clone(), expected: "`&&` or `||`" }),
+  }
+}
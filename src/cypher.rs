@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::query::*;
+
+pub struct Node {
+  alias: String,
+  item: String,
+  mapper: Box<dyn Fn(&str) -> String>,
+}
+
+fn name(name: &str) -> String {
+  if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !name.starts_with(|c: char| c.is_ascii_digit()) {
+    name.to_owned()
+  } else {
+    format!("`{}`", name.replace('`', "``"))
+  }
+}
+
+impl Node {
+  // `item` is the variable list predicates iterate with; it is derived from
+  // the alias so that the two can never be the same name.
+  pub fn new(alias: &str) -> Node {
+    Node { alias: name(alias), item: name(&format!("{}_", alias)), mapper: Box::new(|field| field.replace('.', "_")) }
+  }
+  pub fn map_fields<F: Fn(&str) -> String + 'static>(mut self, mapper: F) -> Node {
+    self.mapper = Box::new(mapper);
+    self
+  }
+  fn property(&self, field: &str) -> String {
+    let path = (self.mapper)(field).split('.').map(name).collect::<Vec<String>>();
+    format!("{}.{}", self.alias, path.join("."))
+  }
+}
+
+impl fmt::Debug for Node {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("Node").field("alias", &self.alias).finish()
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Where {
+  where_clause: String,
+  params: HashMap<String, Value>,
+}
+
+impl fmt::Display for Where {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.where_clause)
+  }
+}
+
+impl Where {
+  pub fn params(&self) -> &HashMap<String, Value> {
+    &self.params
+  }
+  fn param(&mut self, value: &Value) -> String {
+    let name = format!("p{}", self.params.len());
+    self.params.insert(name.clone(), value.clone());
+    format!("${}", name)
+  }
+}
+
+fn compare(field: &str, op: &str, value: &Value, node: &Node, out: &mut Where) -> String {
+  format!("{} {} {}", node.property(field), op, out.param(value))
+}
+
+fn join(queries: &[Query], op: &str, node: &Node, out: &mut Where) -> String {
+  let clauses = queries.iter().map(|q| render(q, node, out)).collect::<Vec<String>>();
+  format!("({})", clauses.join(op))
+}
+
+fn render(query: &Query, node: &Node, out: &mut Where) -> String {
+  match query {
+    Query::And(queries) if queries.is_empty() => "true".to_owned(),
+    Query::Or(queries) if queries.is_empty() => "false".to_owned(),
+    Query::And(queries) => join(queries, " AND ", node, out),
+    Query::Or(queries) => join(queries, " OR ", node, out),
+    Query::Not(query) => match query.as_ref() {
+      Query::None => "false".to_owned(),
+      Query::And(_) | Query::Or(_) => format!("NOT {}", render(query, node, out)),
+      _ => format!("NOT ({})", render(query, node, out)),
+    },
+    Query::Eq { field, value: Value::None } => format!("{} IS NULL", node.property(field)),
+    Query::Neq { field, value: Value::None } => format!("{} IS NOT NULL", node.property(field)),
+    Query::Eq { field, value } => compare(field, "=", value, node, out),
+    Query::Neq { field, value } => compare(field, "<>", value, node, out),
+    Query::Gt { field, value } => compare(field, ">", value, node, out),
+    Query::GtE { field, value } => compare(field, ">=", value, node, out),
+    Query::Lt { field, value } => compare(field, "<", value, node, out),
+    Query::LtE { field, value } => compare(field, "<=", value, node, out),
+    Query::Rx { field, value: Value::String(pattern) } => compare(field, "=~", &Value::String(anchored(pattern)), node, out),
+    Query::Rx { field, value } => compare(field, "=~", value, node, out),
+    Query::In { field, value: value @ Value::Array(_) } => compare(field, "IN", value, node, out),
+    Query::In { field, value } => compare(field, "IN", &Value::Array(Box::new(vec![value.clone()])), node, out),
+    Query::Contains { field, value } => format!("ANY({item} IN {} WHERE {item} = {})", node.property(field), out.param(value), item = node.item),
+    Query::None => "".to_owned(),
+  }
+}
+
+pub trait ToCypher {
+  fn to_cypher(&self, node: &Node) -> Where;
+}
+
+impl ToCypher for Query {
+  fn to_cypher(&self, node: &Node) -> Where {
+    let mut out = Where::default();
+    out.where_clause = render(self, node, &mut out);
+    out
+  }
+}
+
+pub fn to_cypher(query: &dyn ToCypher, node: &Node) -> Where {
+  query.to_cypher(node)
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+  use crate::cypher::{self, *};
+
+  fn params(params: Vec<(&str, Value)>) -> HashMap<String, Value> {
+    params.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+  }
+
+  #[test]
+  fn test_cypher() {
+    let q = query!("b" in [1, 2] || "a" == 5);
+    let q = query!(..q; && "deleted" == false && "source.id" == 5);
+    let result = cypher::to_cypher(&q, &Node::new("n"));
+    assert_eq!(result.to_string(), "((n.b IN $p0 OR n.a = $p1) AND n.deleted = $p2 AND n.source_id = $p3)");
+    assert_eq!(result.params(), &params(vec![("p0", vec![1i64, 2].into()), ("p1", 5.into()), ("p2", false.into()), ("p3", 5.into())]));
+  }
+
+  #[test]
+  fn query_contains_regex_and_null() {
+    let q = Query::and(query!("tags" contains "za"), query!("name" %% "^jo"));
+    let q = Query::and(q, Query::Not(Box::new(Query::Eq { field: "owner".to_owned(), value: Value::None })));
+    let result = cypher::to_cypher(&q, &Node::new("t"));
+    assert_eq!(result.to_string(), "(ANY(t_ IN t.tags WHERE t_ = $p0) AND t.name =~ $p1 AND NOT (t.owner IS NULL))");
+    assert_eq!(result.params(), &params(vec![("p0", "za".into()), ("p1", "(?:jo).*".into())]));
+    let q = Query::Or(vec![query!("a" == 1), Query::Not(Box::new(Query::None))]);
+    assert_eq!(cypher::to_cypher(&q, &Node::new("t")).to_string(), "(t.a = $p0 OR false)");
+    let q = Query::Or(vec![Query::And(vec![]), Query::Or(vec![])]);
+    assert_eq!(cypher::to_cypher(&q, &Node::new("t")).to_string(), "(true OR false)");
+  }
+
+  #[test]
+  fn query_field_mapping() {
+    let node = Node::new("n").map_fields(|field| field.strip_prefix("meta.").unwrap_or(field).to_owned());
+    let q = query!("meta.created by" == "jo" && "source.id" == 5);
+    assert_eq!(cypher::to_cypher(&q, &node).to_string(), "(n.`created by` = $p0 AND n.source.id = $p1)");
+    let q = query!("tags" contains "za");
+    assert_eq!(cypher::to_cypher(&q, &Node::new("x")).to_string(), "ANY(x_ IN x.tags WHERE x_ = $p0)");
+    assert_eq!(cypher::to_cypher(&q, &Node::new("my node")).to_string(), "ANY(`my node_` IN `my node`.tags WHERE `my node_` = $p0)");
+  }
+}
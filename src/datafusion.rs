@@ -0,0 +1,151 @@
+use ::datafusion::common::{Column, ScalarValue};
+use ::datafusion::functions::core::expr_fn::get_field;
+use ::datafusion::functions_nested::expr_fn::{array_has, make_array};
+use ::datafusion::logical_expr::{and, binary_expr, in_list, lit, not, or, Expr, Operator};
+
+use crate::query::*;
+
+pub trait ToLit {
+  fn to_lit(&self) -> Expr;
+}
+
+impl ToLit for Value {
+  fn to_lit(&self) -> Expr {
+    match self {
+      Value::Uuid(u) => lit(u.hyphenated().to_string()),
+      Value::Number(n) => lit(*n),
+      Value::Float(f) => lit(*f),
+      Value::String(s) => lit(s.clone()),
+      Value::Bool(b) => lit(*b),
+      Value::Array(arr) => make_array(arr.iter().map(|v| v.to_lit()).collect()),
+      Value::None => lit(ScalarValue::Null),
+    }
+  }
+}
+
+fn column(field: &str) -> Expr {
+  let mut path = field.split('.');
+  let root = Expr::Column(Column::from_name(path.next().unwrap_or_default()));
+  path.fold(root, get_field)
+}
+
+// Plain ^prefix patterns become LIKE 'prefix%' so parquet scans can prune
+// row groups on them; anything else is evaluated as a regex match.
+fn like_prefix(pattern: &str) -> Option<String> {
+  match pattern.strip_prefix('^') {
+    Some(prefix) if !prefix.is_empty() && !prefix.contains(|c: char| "\\.*+?()[]{}|^$%_".contains(c)) => Some(format!("{}%", prefix)),
+    _ => None,
+  }
+}
+
+pub trait ToExpr {
+  fn to_expr(&self) -> Expr;
+}
+
+impl ToExpr for Query {
+  fn to_expr(&self) -> Expr {
+    match self {
+      Query::And(queries) => queries.iter().map(|q| q.to_expr()).reduce(and).unwrap_or_else(|| lit(true)),
+      Query::Or(queries) => queries.iter().map(|q| q.to_expr()).reduce(or).unwrap_or_else(|| lit(false)),
+      Query::Not(query) if **query == Query::None => lit(false),
+      Query::Not(query) => not(query.to_expr()),
+      Query::Eq { field, value: Value::None } => column(field).is_null(),
+      Query::Neq { field, value: Value::None } => column(field).is_not_null(),
+      Query::Eq { field, value } => column(field).eq(value.to_lit()),
+      Query::Neq { field, value } => column(field).not_eq(value.to_lit()),
+      Query::Gt { field, value } => column(field).gt(value.to_lit()),
+      Query::GtE { field, value } => column(field).gt_eq(value.to_lit()),
+      Query::Lt { field, value } => column(field).lt(value.to_lit()),
+      Query::LtE { field, value } => column(field).lt_eq(value.to_lit()),
+      Query::Rx { field, value } => match value.get_string().as_deref().and_then(like_prefix) {
+        Some(pattern) => column(field).like(lit(pattern)),
+        None => binary_expr(column(field), Operator::RegexMatch, value.to_lit()),
+      },
+      Query::In { field, value: Value::Array(values) } => in_list(column(field), values.iter().map(|v| v.to_lit()).collect(), false),
+      Query::In { field, value } => in_list(column(field), vec![value.to_lit()], false),
+      Query::Contains { field, value } => array_has(column(field), value.to_lit()),
+      Query::None => lit(true),
+    }
+  }
+}
+
+pub fn to_expr(query: &dyn ToExpr) -> Expr {
+  query.to_expr()
+}
+
+#[cfg(test)]
+mod test {
+  use std::sync::Arc;
+  use ::datafusion::arrow::array::{ArrayRef, BooleanArray, Float64Array, Int64Array, ListBuilder, StringArray, StringBuilder, StructArray};
+  use ::datafusion::arrow::datatypes::{DataType, Field, Fields, Schema};
+  use ::datafusion::arrow::record_batch::RecordBatch;
+  use ::datafusion::datasource::MemTable;
+  use ::datafusion::logical_expr::col;
+  use ::datafusion::prelude::SessionContext;
+  use crate::datafusion::{self, *};
+
+  #[test]
+  fn test_expr() {
+    let q = query!("deleted" == false && "amount" >= 10);
+    assert_eq!(datafusion::to_expr(&q), and(col("deleted").eq(lit(false)), col("amount").gt_eq(lit(10i64))));
+    let q = query!("source.id" == 12);
+    assert_eq!(datafusion::to_expr(&q), get_field(col("source"), "id").eq(lit(12i64)));
+    let q = query!("state" %% "^Pen");
+    assert_eq!(datafusion::to_expr(&q), col("state").like(lit("Pen%")));
+  }
+
+  fn context() -> SessionContext {
+    let source = Fields::from(vec![Field::new("id", DataType::Int64, false), Field::new("currency_iso", DataType::Utf8, true)]);
+    let tags = DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)));
+    let schema = Arc::new(Schema::new(vec![
+      Field::new("id", DataType::Int64, false),
+      Field::new("state", DataType::Utf8, true),
+      Field::new("amount", DataType::Float64, false),
+      Field::new("deleted", DataType::Boolean, false),
+      Field::new("source", DataType::Struct(source.clone()), false),
+      Field::new("tags", tags, false),
+    ]));
+    let mut tags = ListBuilder::new(StringBuilder::new());
+    for row in [vec!["za", "us"], vec!["za"], vec![], vec!["uk"]] {
+      for tag in row {
+        tags.values().append_value(tag);
+      }
+      tags.append(true);
+    }
+    let source = StructArray::new(source, vec![
+      Arc::new(Int64Array::from(vec![12, 13, 12, 14])) as ArrayRef,
+      Arc::new(StringArray::from(vec![Some("USD"), Some("ZAR"), Some("USD"), None])) as ArrayRef,
+    ], None);
+    let batch = RecordBatch::try_new(schema.clone(), vec![
+      Arc::new(Int64Array::from(vec![1, 2, 3, 4])),
+      Arc::new(StringArray::from(vec![Some("Pending"), Some("Done"), Some("Done"), None])),
+      Arc::new(Float64Array::from(vec![10.5, 99.0, 5.0, 1.0])),
+      Arc::new(BooleanArray::from(vec![false, false, true, false])),
+      Arc::new(source),
+      Arc::new(tags.finish()),
+    ]).unwrap();
+    let ctx = SessionContext::new();
+    ctx.register_table("transfers", Arc::new(MemTable::try_new(schema, vec![vec![batch]]).unwrap())).unwrap();
+    ctx
+  }
+
+  async fn ids(ctx: &SessionContext, query: &Query) -> Vec<i64> {
+    let df = ctx.table("transfers").await.unwrap().filter(datafusion::to_expr(query)).unwrap().sort(vec![col("id").sort(true, false)]).unwrap();
+    let batches = df.select(vec![col("id")]).unwrap().collect().await.unwrap();
+    batches.iter().flat_map(|b| b.column(0).as_any().downcast_ref::<Int64Array>().unwrap().values().to_vec()).collect()
+  }
+
+  #[tokio::test]
+  async fn query_mem_table() {
+    let ctx = context();
+    assert_eq!(ids(&ctx, &query!("deleted" == false && "source.id" == 12)).await, vec![1]);
+    assert_eq!(ids(&ctx, &query!("state" in ["Pending", "Done"] && "amount" > 6.0f64)).await, vec![1, 2]);
+    assert_eq!(ids(&ctx, &query!("tags" contains "za")).await, vec![1, 2]);
+    assert_eq!(ids(&ctx, &query!("source.currency_iso" %% "^US")).await, vec![1, 3]);
+    assert_eq!(ids(&ctx, &query!("source.currency_iso" %% "R$")).await, vec![2]);
+    assert_eq!(ids(&ctx, &Query::Eq { field: "state".to_owned(), value: Value::None }).await, vec![4]);
+    assert_eq!(ids(&ctx, &Query::Not(Box::new(query!("deleted" == true || "amount" > 50.0f64)))).await, vec![1, 4]);
+    assert_eq!(ids(&ctx, &Query::None).await, vec![1, 2, 3, 4]);
+    assert_eq!(ids(&ctx, &Query::Not(Box::new(Query::None))).await, Vec::<i64>::new());
+  }
+}
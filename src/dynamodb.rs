@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  Unsupported { operation: String, field: String },
+  Regex { field: String, pattern: String },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Unsupported { operation, field } => write!(f, "{} is not supported on {}", operation, field),
+      Error::Regex { field, pattern } => write!(f, "regex {:?} on {} cannot be expressed, only ^prefix patterns map to begins_with", pattern, field),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expression {
+  pub expression: String,
+  pub names: HashMap<String, String>,
+  pub values: HashMap<String, Value>,
+}
+
+impl Expression {
+  fn name(&mut self, field: &str) -> String {
+    field.split('.').map(|segment| {
+      match self.names.iter().find(|(_, name)| name.as_str() == segment) {
+        Some((placeholder, _)) => placeholder.clone(),
+        None => {
+          let placeholder = format!("#n{}", self.names.len());
+          self.names.insert(placeholder.clone(), segment.to_owned());
+          placeholder
+        }
+      }
+    }).collect::<Vec<String>>().join(".")
+  }
+
+  fn value(&mut self, value: &Value) -> String {
+    let placeholder = format!(":v{}", self.values.len());
+    self.values.insert(placeholder.clone(), value.clone());
+    placeholder
+  }
+}
+
+fn prefix(field: &str, value: &Value) -> Result<String, Error> {
+  let pattern = value.get_string().unwrap_or_default();
+  match pattern.strip_prefix('^') {
+    Some(prefix) if !prefix.is_empty() && !prefix.contains(|c: char| "\\.*+?()[]{}|^$".contains(c)) => Ok(prefix.to_owned()),
+    _ => Err(Error::Regex { field: field.to_owned(), pattern }),
+  }
+}
+
+fn begins_with(field: &str, value: &Value, expr: &mut Expression) -> Result<String, Error> {
+  let prefix = prefix(field, value)?;
+  Ok(format!("begins_with({}, {})", expr.name(field), expr.value(&Value::String(prefix))))
+}
+
+fn compare(field: &str, op: &str, value: &Value, expr: &mut Expression) -> String {
+  format!("{} {} {}", expr.name(field), op, expr.value(value))
+}
+
+fn join(queries: &[Query], op: &str, expr: &mut Expression) -> Result<String, Error> {
+  let filters = queries.iter().map(|q| filter(q, expr)).collect::<Result<Vec<String>, Error>>()?;
+  Ok(format!("({})", filters.join(op)))
+}
+
+fn filter(query: &Query, expr: &mut Expression) -> Result<String, Error> {
+  match query {
+    Query::And(queries) if queries.is_empty() => filter(&Query::None, expr),
+    Query::Or(queries) if queries.is_empty() => filter(&Query::Not(Box::new(Query::None)), expr),
+    Query::And(queries) => join(queries, " AND ", expr),
+    Query::Or(queries) => join(queries, " OR ", expr),
+    Query::Not(query) => match query.as_ref() {
+      // Condition expressions have no boolean literals to write false with.
+      Query::None => Err(Error::Unsupported { operation: "FALSE".to_owned(), field: "".to_owned() }),
+      Query::And(_) | Query::Or(_) => Ok(format!("NOT {}", filter(query, expr)?)),
+      _ => Ok(format!("NOT ({})", filter(query, expr)?)),
+    },
+    Query::Eq { field, value: Value::None } => Ok(format!("attribute_not_exists({})", expr.name(field))),
+    Query::Neq { field, value: Value::None } => Ok(format!("attribute_exists({})", expr.name(field))),
+    Query::Eq { field, value } => Ok(compare(field, "=", value, expr)),
+    Query::Neq { field, value } => Ok(compare(field, "<>", value, expr)),
+    Query::Gt { field, value } => Ok(compare(field, ">", value, expr)),
+    Query::GtE { field, value } => Ok(compare(field, ">=", value, expr)),
+    Query::Lt { field, value } => Ok(compare(field, "<", value, expr)),
+    Query::LtE { field, value } => Ok(compare(field, "<=", value, expr)),
+    Query::Rx { field, value } => begins_with(field, value, expr),
+    Query::In { field, value: Value::Array(values) } => {
+      let name = expr.name(field);
+      let values = values.iter().map(|v| expr.value(v)).collect::<Vec<String>>();
+      Ok(format!("{} IN ({})", name, values.join(", ")))
+    },
+    Query::In { field, value } => Ok(format!("{} IN ({})", expr.name(field), expr.value(value))),
+    Query::Contains { field, value } => Ok(format!("contains({}, {})", expr.name(field), expr.value(value))),
+    Query::None => Ok("".to_owned()),
+  }
+}
+
+fn key_condition(query: &Query, expr: &mut Expression) -> Result<String, Error> {
+  match query {
+    Query::And(queries) => {
+      let mut conditions = vec![];
+      let mut queries = queries.iter().peekable();
+      while let Some(query) = queries.next() {
+        match (query, queries.peek()) {
+          (Query::GtE { field, value: low }, Some(Query::LtE { field: f, value: high })) if field == f => {
+            conditions.push(format!("{} BETWEEN {} AND {}", expr.name(field), expr.value(low), expr.value(high)));
+            queries.next();
+          },
+          _ => conditions.push(key_condition(query, expr)?),
+        }
+      }
+      Ok(conditions.join(" AND "))
+    },
+    Query::Eq { field, value } if *value != Value::None => Ok(compare(field, "=", value, expr)),
+    Query::Gt { field, value } => Ok(compare(field, ">", value, expr)),
+    Query::GtE { field, value } => Ok(compare(field, ">=", value, expr)),
+    Query::Lt { field, value } => Ok(compare(field, "<", value, expr)),
+    Query::LtE { field, value } => Ok(compare(field, "<=", value, expr)),
+    Query::Rx { field, value } => begins_with(field, value, expr),
+    Query::Or(_) => Err(Error::Unsupported { operation: "OR".to_owned(), field: "".to_owned() }),
+    Query::Not(_) => Err(Error::Unsupported { operation: "NOT".to_owned(), field: "".to_owned() }),
+    Query::Eq { field, .. } => Err(Error::Unsupported { operation: "attribute_not_exists".to_owned(), field: field.clone() }),
+    Query::Neq { field, .. } => Err(Error::Unsupported { operation: "<>".to_owned(), field: field.clone() }),
+    Query::In { field, .. } => Err(Error::Unsupported { operation: "IN".to_owned(), field: field.clone() }),
+    Query::Contains { field, .. } => Err(Error::Unsupported { operation: "contains".to_owned(), field: field.clone() }),
+    Query::None => Ok("".to_owned()),
+  }
+}
+
+pub trait ToExpression {
+  fn to_filter_expression(&self) -> Result<Expression, Error>;
+  fn to_key_condition_expression(&self) -> Result<Expression, Error>;
+}
+
+impl ToExpression for Query {
+  fn to_filter_expression(&self) -> Result<Expression, Error> {
+    let mut expr = Expression::default();
+    expr.expression = filter(self, &mut expr)?;
+    Ok(expr)
+  }
+  fn to_key_condition_expression(&self) -> Result<Expression, Error> {
+    let mut expr = Expression::default();
+    expr.expression = key_condition(self, &mut expr)?;
+    Ok(expr)
+  }
+}
+
+pub fn to_filter_expression(query: &dyn ToExpression) -> Result<Expression, Error> {
+  query.to_filter_expression()
+}
+
+pub fn to_key_condition_expression(query: &dyn ToExpression) -> Result<Expression, Error> {
+  query.to_key_condition_expression()
+}
+
+#[cfg(test)]
+mod test {
+  use std::collections::HashMap;
+  use crate::dynamodb::{self, *};
+
+  fn names(names: &[(&str, &str)]) -> HashMap<String, String> {
+    names.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+  }
+
+  fn values(values: Vec<(&str, Value)>) -> HashMap<String, Value> {
+    values.into_iter().map(|(k, v)| (k.to_string(), v)).collect()
+  }
+
+  #[test]
+  fn test_filter_expression() {
+    let q = query!("tags" contains "za" || "source.id" == 5);
+    let q = Query::and(query!("state" in ["Pending", "Done"]), Query::and(query!("deleted" == false), q));
+    let expr = dynamodb::to_filter_expression(&q).unwrap();
+    assert_eq!(expr.expression, "(#n0 IN (:v0, :v1) AND #n1 = :v2 AND (contains(#n2, :v3) OR #n3.#n4 = :v4))");
+    assert_eq!(expr.names, names(&[("#n0", "state"), ("#n1", "deleted"), ("#n2", "tags"), ("#n3", "source"), ("#n4", "id")]));
+    assert_eq!(expr.values, values(vec![(":v0", "Pending".into()), (":v1", "Done".into()), (":v2", false.into()), (":v3", "za".into()), (":v4", 5.into())]));
+  }
+
+  #[test]
+  fn query_exists_and_prefix() {
+    let q = Query::and(Query::Neq { field: "owner".to_owned(), value: Value::None }, Query::Not(Box::new(Query::Rx { field: "owner".to_owned(), value: "^test-".into() })));
+    let expr = dynamodb::to_filter_expression(&q).unwrap();
+    assert_eq!(expr.expression, "(attribute_exists(#n0) AND NOT (begins_with(#n0, :v0)))");
+    assert_eq!(expr.names, names(&[("#n0", "owner")]));
+    assert_eq!(expr.values, values(vec![(":v0", "test-".into())]));
+    let q = query!("owner" %% "^te.t");
+    assert_eq!(dynamodb::to_filter_expression(&q), Err(dynamodb::Error::Regex { field: "owner".to_owned(), pattern: "^te.t".to_owned() }));
+    assert_eq!(dynamodb::to_filter_expression(&Query::Not(Box::new(Query::None))), Err(dynamodb::Error::Unsupported { operation: "FALSE".to_owned(), field: "".to_owned() }));
+  }
+
+  #[test]
+  fn test_key_condition_expression() {
+    let q = Query::and(query!("pk" == "user#1"), Query::and(query!("sk" >= 10), Query::LtE { field: "sk".to_owned(), value: 20.into() }));
+    let expr = dynamodb::to_key_condition_expression(&q).unwrap();
+    assert_eq!(expr.expression, "#n0 = :v0 AND #n1 BETWEEN :v1 AND :v2");
+    assert_eq!(expr.values, values(vec![(":v0", "user#1".into()), (":v1", 10.into()), (":v2", 20.into())]));
+    let q = query!("pk" == "user#1" || "pk" == "user#2");
+    assert_eq!(dynamodb::to_key_condition_expression(&q), Err(dynamodb::Error::Unsupported { operation: "OR".to_owned(), field: "".to_owned() }));
+  }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value as Json};
+
+use crate::query::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+  Keyword,
+  Text,
+  TextWithKeyword,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Hints {
+  fields: HashMap<String, FieldType>,
+}
+
+impl Hints {
+  pub fn new() -> Hints {
+    Hints::default()
+  }
+  pub fn field(mut self, field: &str, field_type: FieldType) -> Hints {
+    self.fields.insert(field.to_owned(), field_type);
+    self
+  }
+  pub fn field_type(&self, field: &str) -> FieldType {
+    self.fields.get(field).cloned().unwrap_or(FieldType::Keyword)
+  }
+  fn exact(&self, field: &str) -> String {
+    match self.field_type(field) {
+      FieldType::TextWithKeyword => format!("{}.keyword", field),
+      _ => field.to_owned(),
+    }
+  }
+}
+
+pub trait ToJson {
+  fn to_json(&self) -> Json;
+}
+
+impl ToJson for Value {
+  fn to_json(&self) -> Json {
+    match self {
+      Value::Uuid(u) => json!(u.hyphenated().to_string()),
+      Value::Number(n) => json!(n),
+      Value::Float(f) => json!(f),
+      Value::String(s) => json!(s),
+      Value::Bool(b) => json!(b),
+      Value::Array(arr) => Json::Array(arr.iter().map(|v| v.to_json()).collect()),
+      Value::None => Json::Null,
+    }
+  }
+}
+
+pub trait ToElastic {
+  fn to_elastic(&self) -> Json {
+    self.to_elastic_with_hints(&Hints::new())
+  }
+  fn to_elastic_with_hints(&self, hints: &Hints) -> Json;
+}
+
+fn term(field: &str, value: &Value, hints: &Hints) -> Json {
+  match hints.field_type(field) {
+    FieldType::Text => json!({ "match_phrase": { field: value.to_json() } }),
+    _ => json!({ "term": { hints.exact(field): value.to_json() } }),
+  }
+}
+
+fn range(field: &str, op: &str, value: &Value, hints: &Hints) -> Json {
+  json!({ "range": { hints.exact(field): { op: value.to_json() } } })
+}
+
+fn exists(field: &str) -> Json {
+  json!({ "exists": { "field": field } })
+}
+
+fn must_not(query: Json) -> Json {
+  json!({ "bool": { "must_not": [query] } })
+}
+
+impl ToElastic for Query {
+  fn to_elastic_with_hints(&self, hints: &Hints) -> Json {
+    match self {
+      Query::And(queries) if queries.is_empty() => Query::None.to_elastic_with_hints(hints),
+      Query::Or(queries) if queries.is_empty() => Query::Not(Box::new(Query::None)).to_elastic_with_hints(hints),
+      Query::And(_) => {
+        let mut queries = vec![];
+        flatten(self, true, &mut queries);
+        json!({ "bool": { "filter": queries.iter().map(|q| q.to_elastic_with_hints(hints)).collect::<Vec<Json>>() } })
+      },
+      Query::Or(_) => {
+        let mut queries = vec![];
+        flatten(self, false, &mut queries);
+        json!({ "bool": { "should": queries.iter().map(|q| q.to_elastic_with_hints(hints)).collect::<Vec<Json>>(), "minimum_should_match": 1 } })
+      },
+      Query::Not(query) if **query == Query::None => json!({ "match_none": {} }),
+      Query::Not(query) => must_not(query.to_elastic_with_hints(hints)),
+      Query::Eq { field, value: Value::None } => must_not(exists(field)),
+      Query::Neq { field, value: Value::None } => exists(field),
+      Query::Eq { field, value } => term(field, value, hints),
+      Query::Neq { field, value } => must_not(term(field, value, hints)),
+      Query::Gt { field, value } => range(field, "gt", value, hints),
+      Query::GtE { field, value } => range(field, "gte", value, hints),
+      Query::Lt { field, value } => range(field, "lt", value, hints),
+      Query::LtE { field, value } => range(field, "lte", value, hints),
+      // Lucene regexps match the whole term and have neither anchors nor
+      // backreferences, so plain groups are safe here.
+      Query::Rx { field, value: Value::String(pattern) } => json!({ "regexp": { hints.exact(field): { "value": anchored_with(pattern, "(") } } }),
+      Query::Rx { field, value } => json!({ "regexp": { hints.exact(field): { "value": value.to_json() } } }),
+      Query::In { field, value } => json!({ "terms": { hints.exact(field): value.to_json() } }),
+      Query::Contains { field, value } => term(field, value, hints),
+      Query::None => json!({ "match_all": {} }),
+    }
+  }
+}
+
+pub fn to_elastic(query: &dyn ToElastic) -> Json {
+  query.to_elastic()
+}
+
+pub fn to_elastic_with_hints(query: &dyn ToElastic, hints: &Hints) -> Json {
+  query.to_elastic_with_hints(hints)
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::json;
+  use crate::elastic::{self, *};
+
+  #[test]
+  fn test_elastic() {
+    let q = query!("deleted" == false && "b" == 5 && ("a" == 5 || "b" < 5));
+    let q_r = json!({ "bool": { "filter": [
+      { "term": { "deleted": false } },
+      { "term": { "b": 5 } },
+      { "bool": { "should": [ { "term": { "a": 5 } }, { "range": { "b": { "lt": 5 } } } ], "minimum_should_match": 1 } },
+    ] } });
+    assert_eq!(elastic::to_elastic(&q), q_r);
+    assert_eq!(elastic::to_elastic(&Query::None), json!({ "match_all": {} }));
+    assert_eq!(elastic::to_elastic(&Query::Not(Box::new(Query::None))), json!({ "match_none": {} }));
+  }
+
+  #[test]
+  fn query_in_and_negation() {
+    let q = query!("state" in ["Pending", "Done"] && "kind" != "test");
+    let q = Query::and(q, Query::Eq { field: "deleted_at".to_owned(), value: Value::None });
+    let q_r = json!({ "bool": { "filter": [
+      { "terms": { "state": ["Pending", "Done"] } },
+      { "bool": { "must_not": [ { "term": { "kind": "test" } } ] } },
+      { "bool": { "must_not": [ { "exists": { "field": "deleted_at" } } ] } },
+    ] } });
+    assert_eq!(elastic::to_elastic(&q), q_r);
+  }
+
+  #[test]
+  fn query_field_hints() {
+    let hints = Hints::new().field("name", FieldType::TextWithKeyword).field("bio", FieldType::Text);
+    let q = query!("name" == "Jo" && "bio" == "likes rust" && "name" >= "J");
+    let q_r = json!({ "bool": { "filter": [
+      { "term": { "name.keyword": "Jo" } },
+      { "match_phrase": { "bio": "likes rust" } },
+      { "range": { "name.keyword": { "gte": "J" } } },
+    ] } });
+    assert_eq!(elastic::to_elastic_with_hints(&q, &hints), q_r);
+    let q = Query::Rx { field: "name".to_owned(), value: "jo.*".into() };
+    assert_eq!(elastic::to_elastic_with_hints(&q, &hints), json!({ "regexp": { "name.keyword": { "value": ".*(jo.*).*" } } }));
+    let q = query!("name" %% "^jo");
+    assert_eq!(elastic::to_elastic_with_hints(&q, &hints), json!({ "regexp": { "name.keyword": { "value": "(jo).*" } } }));
+    let q = query!("name" %% "^jo|on$");
+    assert_eq!(elastic::to_elastic_with_hints(&q, &hints), json!({ "regexp": { "name.keyword": { "value": "((jo).*|.*(on))" } } }));
+  }
+}
@@ -1,5 +1,16 @@
 #[cfg(feature = "mongo")] #[macro_use] extern crate bson;
-#[cfg(feature = "parse")] extern crate lexer;
+#[cfg(feature = "parse")] extern crate querylib_grammar;
+#[cfg(feature = "derive")] extern crate self as querylib;
+#[cfg(feature = "derive")] pub use querylib_derive::{q, Queryable};
 #[macro_use] pub mod query;
 #[cfg(feature = "mongo")] pub mod mongo;
-#[cfg(feature = "postgres")] pub mod postgres;
\ No newline at end of file
+#[cfg(feature = "postgres")] pub mod postgres;
+#[cfg(feature = "elastic")] pub mod elastic;
+#[cfg(feature = "sqlite")] pub mod sqlite;
+#[cfg(feature = "dynamodb")] pub mod dynamodb;
+#[cfg(feature = "datafusion")] pub mod datafusion;
+#[cfg(feature = "tantivy")] pub mod tantivy;
+#[cfg(feature = "meilisearch")] pub mod meilisearch;
+#[cfg(feature = "typesense")] pub mod typesense;
+#[cfg(feature = "cypher")] pub mod cypher;
+#[cfg(feature = "mango")] pub mod mango;
\ No newline at end of file
@@ -0,0 +1,83 @@
+use serde_json::{json, Map, Value as Json};
+
+use crate::query::*;
+
+fn to_json(value: &Value) -> Json {
+  match value {
+    Value::Uuid(u) => json!(u.hyphenated().to_string()),
+    Value::Number(n) => json!(n),
+    Value::Float(f) => json!(f),
+    Value::String(s) => json!(s),
+    Value::Bool(b) => json!(b),
+    Value::Array(arr) => Json::Array(arr.iter().map(to_json).collect()),
+    Value::None => Json::Null,
+  }
+}
+
+fn field(field: &str, op: &str, value: Json) -> Json {
+  let mut ops = Map::new();
+  ops.insert(op.to_owned(), value);
+  let mut selector = Map::new();
+  selector.insert(field.to_owned(), Json::Object(ops));
+  Json::Object(selector)
+}
+
+pub trait ToMango {
+  fn to_mango(&self) -> Json;
+}
+
+impl ToMango for Query {
+  fn to_mango(&self) -> Json {
+    match self {
+      Query::And(queries) if queries.is_empty() => Query::None.to_mango(),
+      Query::Or(queries) if queries.is_empty() => Query::Not(Box::new(Query::None)).to_mango(),
+      Query::And(queries) | Query::Or(queries) => json!({ self.operator(): queries.iter().map(|q| q.to_mango()).collect::<Vec<Json>>() }),
+      // Every document has an _id, so this matches none of them.
+      Query::Not(query) if **query == Query::None => field("_id", "$exists", json!(false)),
+      Query::Not(query) => json!({ self.operator(): query.to_mango() }),
+      Query::Eq { field: f, value: Value::None } => field(f, "$exists", json!(false)),
+      Query::Neq { field: f, value: Value::None } => field(f, "$exists", json!(true)),
+      Query::Contains { field: f, value } => field(f, self.operator(), json!({ "$eq": to_json(value) })),
+      Query::Eq { field: f, value } | Query::Neq { field: f, value } | Query::Gt { field: f, value } | Query::GtE { field: f, value }
+        | Query::Lt { field: f, value } | Query::LtE { field: f, value } | Query::Rx { field: f, value }
+        | Query::In { field: f, value } => field(f, self.operator(), to_json(value)),
+      Query::None => json!({}),
+    }
+  }
+}
+
+impl ToMango for QuerySpec {
+  fn to_mango(&self) -> Json {
+    self.filter.to_mango()
+  }
+}
+
+pub fn to_mango(query: &dyn ToMango) -> Json {
+  query.to_mango()
+}
+
+#[cfg(test)]
+mod test {
+  use serde_json::json;
+  use crate::mango::{self, *};
+
+  #[test]
+  fn test_mango() {
+    let q = query!("deleted" == false && "b" in [5, 6, 7]);
+    assert_eq!(mango::to_mango(&q), json!({ "$and": [ { "deleted": { "$eq": false } }, { "b": { "$in": [5, 6, 7] } } ] }));
+    let q = query!("a" >= 1.5f64 || "source.id" != "x");
+    assert_eq!(mango::to_mango(&q), json!({ "$or": [ { "a": { "$gte": 1.5 } }, { "source.id": { "$ne": "x" } } ] }));
+    assert_eq!(mango::to_mango(&Query::None), json!({}));
+    assert_eq!(mango::to_mango(&Query::Not(Box::new(Query::None))), json!({ "_id": { "$exists": false } }));
+  }
+
+  #[test]
+  fn query_regex_elem_match_and_not() {
+    let q = Query::and(query!("tags" contains "za"), query!("name" %% "^jo"));
+    assert_eq!(mango::to_mango(&q), json!({ "$and": [ { "tags": { "$elemMatch": { "$eq": "za" } } }, { "name": { "$regex": "^jo" } } ] }));
+    let q = Query::Not(Box::new(Query::Eq { field: "deleted_at".to_owned(), value: Value::None }));
+    assert_eq!(mango::to_mango(&q), json!({ "$not": { "deleted_at": { "$exists": false } } }));
+    let q = QuerySpec::new(query!("state" == "Done")).limit(10);
+    assert_eq!(mango::to_mango(&q), json!({ "state": { "$eq": "Done" } }));
+  }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  Unsupported { operation: String, field: String },
+  UnsupportedValue { field: String, value: Value },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Unsupported { operation, field } => write!(f, "{} is not supported on {}", operation, field),
+      Error::UnsupportedValue { field, value } => write!(f, "{:?} cannot be compared with {}", value, field),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+fn quote(s: &str) -> String {
+  format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+const KEYWORDS: &[&str] = &["AND", "OR", "NOT", "IN", "TO", "EXISTS", "IS", "NULL", "EMPTY", "CONTAINS"];
+
+// Attribute names are quoted like string values unless they are plain
+// dotted identifiers, which keeps spaces and operators in a name from
+// changing the filter.
+fn attribute(field: &str) -> String {
+  let plain = field.split('.').all(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'));
+  if plain && !KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(field)) {
+    field.to_owned()
+  } else {
+    quote(field)
+  }
+}
+
+fn literal(field: &str, value: &Value) -> Result<String, Error> {
+  match value {
+    Value::Uuid(u) => Ok(format!("\"{}\"", u.hyphenated())),
+    Value::Number(n) => Ok(n.to_string()),
+    Value::Float(f) => Ok(f.to_string()),
+    Value::String(s) => Ok(quote(s)),
+    Value::Bool(b) => Ok(b.to_string()),
+    Value::Array(_) | Value::None => Err(Error::UnsupportedValue { field: field.to_owned(), value: value.clone() }),
+  }
+}
+
+fn compare(field: &str, op: &str, value: &Value) -> Result<String, Error> {
+  Ok(format!("{} {} {}", attribute(field), op, literal(field, value)?))
+}
+
+fn group(query: &Query) -> Result<String, Error> {
+  match query {
+    Query::And(_) | Query::Or(_) => Ok(format!("({})", query.to_filter()?)),
+    _ => query.to_filter(),
+  }
+}
+
+fn join(query: &Query, and: bool) -> Result<String, Error> {
+  let mut queries = vec![];
+  flatten(query, and, &mut queries);
+  let filters = queries.into_iter().map(group).collect::<Result<Vec<String>, Error>>()?;
+  Ok(filters.join(if and { " AND " } else { " OR " }))
+}
+
+pub trait ToFilter {
+  fn to_filter(&self) -> Result<String, Error>;
+}
+
+impl ToFilter for Query {
+  fn to_filter(&self) -> Result<String, Error> {
+    match self {
+      Query::And(queries) if queries.is_empty() => Query::None.to_filter(),
+      Query::Or(queries) if queries.is_empty() => Query::Not(Box::new(Query::None)).to_filter(),
+      Query::And(_) => join(self, true),
+      Query::Or(_) => join(self, false),
+      Query::Not(query) => match query.as_ref() {
+        // Filters have no boolean literals to write false with.
+        Query::None => Err(Error::Unsupported { operation: "FALSE".to_owned(), field: "".to_owned() }),
+        _ => Ok(format!("NOT {}", group(query)?)),
+      },
+      Query::Eq { field, value: Value::None } => Ok(format!("{} NOT EXISTS", attribute(field))),
+      Query::Neq { field, value: Value::None } => Ok(format!("{} EXISTS", attribute(field))),
+      Query::Eq { field, value } => compare(field, "=", value),
+      Query::Neq { field, value } => compare(field, "!=", value),
+      Query::Gt { field, value } => compare(field, ">", value),
+      Query::GtE { field, value } => compare(field, ">=", value),
+      Query::Lt { field, value } => compare(field, "<", value),
+      Query::LtE { field, value } => compare(field, "<=", value),
+      Query::Rx { field, .. } => Err(Error::Unsupported { operation: "regex".to_owned(), field: field.clone() }),
+      Query::In { field, value: Value::Array(values) } => {
+        let values = values.iter().map(|v| literal(field, v)).collect::<Result<Vec<String>, Error>>()?;
+        Ok(format!("{} IN [{}]", attribute(field), values.join(", ")))
+      },
+      Query::In { field, value } => Ok(format!("{} IN [{}]", attribute(field), literal(field, value)?)),
+      Query::Contains { field, value } => compare(field, "=", value),
+      Query::None => Ok("".to_owned()),
+    }
+  }
+}
+
+pub fn to_filter(query: &dyn ToFilter) -> Result<String, Error> {
+  query.to_filter()
+}
+
+#[cfg(test)]
+mod test {
+  use crate::meilisearch::{self, *};
+
+  #[test]
+  fn test_filter() {
+    let q = Query::and(query!("a" == 1), query!("b" > 2 || "c" in ["x", "y"]));
+    assert_eq!(meilisearch::to_filter(&q).unwrap(), "a = 1 AND (b > 2 OR c IN [\"x\", \"y\"])");
+    let q = query!("deleted" == false && "source.id" == 5 && "tags" contains "za");
+    assert_eq!(meilisearch::to_filter(&q).unwrap(), "deleted = false AND source.id = 5 AND tags = \"za\"");
+    let q = Query::Not(Box::new(query!("state" == "Done" || "amount" >= 2.5f64)));
+    assert_eq!(meilisearch::to_filter(&q).unwrap(), "NOT (state = \"Done\" OR amount >= 2.5)");
+    assert_eq!(meilisearch::to_filter(&Query::Eq { field: "deleted_at".to_owned(), value: Value::None }).unwrap(), "deleted_at NOT EXISTS");
+  }
+
+  #[test]
+  fn query_escaping_and_errors() {
+    let q = query!("name" == "say \"hi\" \\o/");
+    assert_eq!(meilisearch::to_filter(&q).unwrap(), "name = \"say \\\"hi\\\" \\\\o/\"");
+    let q = query!("name" %% "^jo");
+    assert_eq!(meilisearch::to_filter(&q), Err(meilisearch::Error::Unsupported { operation: "regex".to_owned(), field: "name".to_owned() }));
+    let q = Query::Gt { field: "a".to_owned(), value: Value::None };
+    assert_eq!(meilisearch::to_filter(&q), Err(meilisearch::Error::UnsupportedValue { field: "a".to_owned(), value: Value::None }));
+    assert_eq!(meilisearch::to_filter(&Query::Not(Box::new(Query::None))), Err(meilisearch::Error::Unsupported { operation: "FALSE".to_owned(), field: "".to_owned() }));
+    let q = query!("created by" == 1 && "a = 1 OR \"b\"" == 2 && "not" == 3 && "ship-to.zip" == 4);
+    assert_eq!(meilisearch::to_filter(&q).unwrap(), r#""created by" = 1 AND "a = 1 OR \"b\"" = 2 AND "not" = 3 AND ship-to.zip = 4"#);
+    assert_eq!(meilisearch::to_filter(&Query::In { field: "a b".to_owned(), value: 1.into() }).unwrap(), "\"a b\" IN [1]");
+  }
+}
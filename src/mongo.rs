@@ -1,62 +1,379 @@
-use bson::Bson::Null;
+use std::fmt;
+
+use bson::Bson::{self, Null};
 use bson::Document;
+use bson::spec::BinarySubtype;
 use crate::query::*;
+use crate::query::update::{self, UpdateError};
 
 pub trait FromOperand {
   fn from_op(self: &Self, op: &str) -> Document;
 }
 impl FromOperand for Value {
   fn from_op(&self, op: &str) -> Document { 
-    match self {
-      Value::Uuid(u) => doc!(op: u.hyphenated().to_string()),
-      Value::String(s) => doc!(op: s),
-      Value::Number(n) => doc!(op: n),
-      Value::Float(f) => doc!(op: f),
-      Value::Bool(b) => doc!(op: b),
-      Value::Array(arr) => {
-        match &arr.clone().into_iter().next() {
-          Some(Value::String(_)) => {
-            doc!(op: arr.clone().into_iter().map(|v| v.get_string()).flatten().collect::<Vec<String>>())
-          },
-          Some(Value::Number(_)) => {
-            doc!(op: arr.clone().into_iter().map(|v| v.get_number()).flatten().collect::<Vec<i64>>())
-          },
-          Some(Value::Float(_)) => {
-            doc!(op: arr.clone().into_iter().map(|v| v.get_float()).flatten().collect::<Vec<f64>>())
-          },
-          _ => doc!(op: Null)
-        }
-      },
-      Value::None => doc!(op: Null),
-    }
+    doc!(op: self.to_bson_value())
+  }
+}
+
+// Uuids are written as strings unless the caller stores them as binary,
+// which is the only form from_bson reads them from. Filters migrated with
+// from_bson should be written back with UuidFormat::Binary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UuidFormat {
+  String,
+  Binary,
+}
+
+fn bson_value(value: &Value, uuids: UuidFormat) -> Bson {
+  match value {
+    Value::Uuid(u) => match uuids {
+      UuidFormat::String => Bson::String(u.hyphenated().to_string()),
+      UuidFormat::Binary => Bson::Binary(bson::Binary { subtype: BinarySubtype::Uuid, bytes: u.as_bytes().to_vec() }),
+    },
+    Value::String(s) => Bson::String(s.clone()),
+    Value::Number(n) => Bson::Int64(*n),
+    Value::Float(f) => Bson::Double(*f),
+    Value::Bool(b) => Bson::Boolean(*b),
+    Value::Array(arr) => Bson::Array(arr.iter().map(|v| bson_value(v, uuids)).collect()),
+    Value::None => Null,
+  }
+}
+
+pub trait ToBsonValue {
+  fn to_bson_value(&self) -> Bson;
+}
+impl ToBsonValue for Value {
+  fn to_bson_value(&self) -> Bson {
+    bson_value(self, UuidFormat::String)
   }
 }
 pub trait ToBson {
-  fn to_bson(&self) -> Document;
+  fn to_bson(&self) -> Document {
+    self.to_bson_with_uuids(UuidFormat::String)
+  }
+  fn to_bson_with_uuids(&self, uuids: UuidFormat) -> Document;
 }
 impl ToBson for Query {
-  fn to_bson(&self) -> Document {
+  fn to_bson_with_uuids(&self, uuids: UuidFormat) -> Document {
     match self {
-      Query::And { left, right } => doc!("$and": [ left.to_bson() , right.to_bson() ]),
-      Query::Or { left, right } => doc!("$or": [ left.to_bson() , right.to_bson() ]),
-      Query::Eq { field, value } => doc!( field : value.from_op("$eq")),
-      Query::Neq { field, value } => doc!( field : value.from_op("$neq")),
-      Query::Gt { field, value } => doc!( field : value.from_op("$gt")),
-      Query::GtE { field, value } => doc!( field : value.from_op("$gte")),
-      Query::Lt { field, value } => doc!( field : value.from_op("$lt")),
-      Query::LtE { field, value } => doc!( field : value.from_op("$lte")),
-      Query::Rx { field, value } => doc!( field : value.from_op("$regex")),
-      Query::In { field, value } => doc!( field : value.from_op("$in")),
-      Query::Contains { field, value } => doc!( field : value.from_op("$elemMatch")),
+      Query::And(queries) if queries.is_empty() => Query::None.to_bson(),
+      Query::Or(queries) if queries.is_empty() => Query::Not(Box::new(Query::None)).to_bson(),
+      Query::And(queries) | Query::Or(queries) => doc!(self.operator(): queries.iter().map(|q| Bson::Document(q.to_bson_with_uuids(uuids))).collect::<Vec<Bson>>()),
+      // Every document has an _id, so this matches none of them.
+      Query::Not(query) if **query == Query::None => doc!("_id": { "$exists": false }),
+      Query::Not(query) => doc!("$nor": [ query.to_bson_with_uuids(uuids) ]),
+      Query::Eq { field, value } | Query::Neq { field, value } | Query::Gt { field, value } | Query::GtE { field, value }
+        | Query::Lt { field, value } | Query::LtE { field, value } | Query::Rx { field, value } | Query::In { field, value }
+        | Query::Contains { field, value } => doc!( field : { self.operator(): bson_value(value, uuids) }),
       Query::None => doc!(),
     }
   }
 }
 
+impl ToBson for QuerySpec {
+  fn to_bson_with_uuids(&self, uuids: UuidFormat) -> Document {
+    self.filter.to_bson_with_uuids(uuids)
+  }
+}
+
+pub trait ToProjection {
+  fn to_projection(&self) -> Result<Document, Error>;
+}
+// Mongo refuses projections that both include and exclude fields, except
+// for excluding _id next to an include list.
+impl ToProjection for Projection {
+  fn to_projection(&self) -> Result<Document, Error> {
+    if !self.include.is_empty() {
+      if let Some(field) = self.exclude.iter().find(|f| f.as_str() != "_id") {
+        return Err(Error::MixedProjection { path: field.clone() });
+      }
+    }
+    let mut projection = Document::new();
+    for field in &self.include {
+      projection.insert(field.clone(), 1i32);
+    }
+    for field in &self.exclude {
+      projection.insert(field.clone(), 0i32);
+    }
+    Ok(projection)
+  }
+}
+impl ToProjection for QuerySpec {
+  fn to_projection(&self) -> Result<Document, Error> {
+    self.projection.to_projection()
+  }
+}
+
+fn sort_document(sort: &[Sort]) -> Document {
+  let mut document = Document::new();
+  for s in sort {
+    match s.direction {
+      Direction::Asc => document.insert(s.field.clone(), 1i32),
+      Direction::Desc => document.insert(s.field.clone(), -1i32),
+    };
+  }
+  document
+}
+
+pub trait ToFindOptions {
+  fn to_sort(&self) -> Document;
+  fn to_find_options(&self) -> Result<Document, Error>;
+}
+impl ToFindOptions for QuerySpec {
+  fn to_sort(&self) -> Document {
+    sort_document(&self.sort)
+  }
+  fn to_find_options(&self) -> Result<Document, Error> {
+    let mut options = Document::new();
+    if !self.sort.is_empty() {
+      options.insert("sort", self.to_sort());
+    }
+    if !self.projection.is_empty() {
+      options.insert("projection", self.to_projection()?);
+    }
+    if let Some(limit) = self.limit {
+      options.insert("limit", limit as i64);
+    }
+    if let Some(offset) = self.offset {
+      options.insert("skip", offset as i64);
+    }
+    Ok(options)
+  }
+}
+
+pub trait ToUpdate {
+  fn to_update(&self) -> Result<Document, UpdateError>;
+}
+impl ToUpdate for Vec<Update> {
+  fn to_update(&self) -> Result<Document, UpdateError> {
+    update::check(self)?;
+    let mut update = Document::new();
+    for u in self {
+      let (op, value) = match u {
+        Update::Set { value, .. } => ("$set", value.to_bson_value()),
+        Update::Unset { .. } => ("$unset", Bson::String("".to_owned())),
+        Update::Inc { value, .. } => ("$inc", value.to_bson_value()),
+        Update::Push { value, .. } => ("$push", value.to_bson_value()),
+        Update::Pull { value, .. } => ("$pull", value.to_bson_value()),
+        Update::AddToSet { value, .. } => ("$addToSet", value.to_bson_value()),
+      };
+      if !update.contains_key(op) {
+        update.insert(op, Document::new());
+      }
+      if let Ok(fields) = update.get_document_mut(op) {
+        fields.insert(u.field(), value);
+      }
+    }
+    Ok(update)
+  }
+}
+
+pub trait ToPipeline {
+  fn to_pipeline(&self) -> Vec<Document>;
+}
+impl ToPipeline for Aggregate {
+  fn to_pipeline(&self) -> Vec<Document> {
+    let mut pipeline = vec![];
+    if self.filter != Query::None {
+      pipeline.push(doc!("$match": self.filter.to_bson()));
+    }
+    // Group keys cannot contain dots, so paths are percent-encoded; simply
+    // replacing the dots would make "a.b" and "a_b" share a key.
+    let key = |field: &str| field.replace('%', "%25").replace('.', "%2E").replace('$', "%24");
+    let mut id = Document::new();
+    for field in &self.group_by {
+      id.insert(key(field), format!("${}", field));
+    }
+    let mut group = doc!("_id": if id.is_empty() { Null } else { id.into() });
+    let mut project = doc!("_id": 0);
+    for field in &self.group_by {
+      project.insert(field.clone(), format!("$_id.{}", key(field)));
+    }
+    for (alias, accumulator) in &self.accumulators {
+      let acc = match accumulator {
+        Accumulator::Count => doc!("$sum": 1),
+        Accumulator::Sum(field) => doc!("$sum": format!("${}", field)),
+        Accumulator::Avg(field) => doc!("$avg": format!("${}", field)),
+        Accumulator::Min(field) => doc!("$min": format!("${}", field)),
+        Accumulator::Max(field) => doc!("$max": format!("${}", field)),
+      };
+      group.insert(alias.clone(), acc);
+      project.insert(alias.clone(), 1);
+    }
+    pipeline.push(doc!("$group": group));
+    pipeline.push(doc!("$project": project));
+    if !self.sort.is_empty() {
+      pipeline.push(doc!("$sort": sort_document(&self.sort)));
+    }
+    if let Some(limit) = self.limit {
+      pipeline.push(doc!("$limit": limit as i64));
+    }
+    pipeline
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  UnsupportedOperator { path: String, operator: String },
+  UnsupportedValue { path: String },
+  MixedProjection { path: String },
+  EmptyArray { path: String },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::UnsupportedOperator { path, operator } => write!(f, "unsupported operator {} at {}", operator, path),
+      Error::UnsupportedValue { path } => write!(f, "unsupported value at {}", path),
+      Error::MixedProjection { path } => write!(f, "projection cannot exclude {} while including other fields", path),
+      Error::EmptyArray { path } => write!(f, "{} needs at least one document", path),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+fn join_path(path: &str, key: &str) -> String {
+  match path {
+    "" => key.to_owned(),
+    _ => format!("{}.{}", path, key),
+  }
+}
+
+fn value_from_bson(value: &Bson, path: &str) -> Result<Value, Error> {
+  match value {
+    Bson::String(s) => Ok(Value::String(s.clone())),
+    Bson::Int32(n) => Ok(Value::Number(*n as i64)),
+    Bson::Int64(n) => Ok(Value::Number(*n)),
+    Bson::Double(f) => Ok(Value::Float(*f)),
+    Bson::Boolean(b) => Ok(Value::Bool(*b)),
+    Bson::Null => Ok(Value::None),
+    Bson::RegularExpression(rx) => match rx.options.as_str() {
+      "" => Ok(Value::String(rx.pattern.clone())),
+      options => Ok(Value::String(format!("(?{}){}", options, rx.pattern))),
+    },
+    Bson::Binary(b) if b.subtype == BinarySubtype::Uuid => {
+      uuid::Uuid::from_slice(&b.bytes).map(Value::Uuid).map_err(|_| Error::UnsupportedValue { path: path.to_owned() })
+    },
+    Bson::Array(arr) => {
+      let values = arr.iter().enumerate()
+        .map(|(i, v)| value_from_bson(v, &join_path(path, &i.to_string())))
+        .collect::<Result<Vec<Value>, Error>>()?;
+      Ok(Value::Array(Box::new(values)))
+    },
+    _ => Err(Error::UnsupportedValue { path: path.to_owned() }),
+  }
+}
+
+// MongoDB rejects $and, $or and $nor with an empty array, so they are not
+// given a meaning here either.
+fn documents(value: &Bson, path: &str) -> Result<Vec<Query>, Error> {
+  match value {
+    Bson::Array(arr) if arr.is_empty() => Err(Error::EmptyArray { path: path.to_owned() }),
+    Bson::Array(arr) => arr.iter().enumerate().map(|(i, v)| match v {
+      Bson::Document(d) => filter_from_bson(d, &join_path(path, &i.to_string())),
+      _ => Err(Error::UnsupportedValue { path: join_path(path, &i.to_string()) }),
+    }).collect(),
+    _ => Err(Error::UnsupportedValue { path: path.to_owned() }),
+  }
+}
+
+fn is_operator_document(value: &Bson) -> bool {
+  match value {
+    Bson::Document(d) => !d.is_empty() && d.keys().all(|k| k.starts_with('$')),
+    _ => false,
+  }
+}
+
+fn field_from_bson(field: &str, ops: &Document, path: &str) -> Result<Query, Error> {
+  let mut queries = vec![];
+  for (op, value) in ops {
+    let op_path = join_path(path, op);
+    let query = match op.as_str() {
+      "$eq" => Query::Eq { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$ne" | "$neq" => Query::Neq { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$gt" => Query::Gt { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$gte" => Query::GtE { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$lt" => Query::Lt { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$lte" => Query::LtE { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$in" => Query::In { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      "$nin" => Query::Not(Box::new(Query::In { field: field.to_owned(), value: value_from_bson(value, &op_path)? })),
+      "$regex" => {
+        let value = match (value_from_bson(value, &op_path)?, ops.get_str("$options")) {
+          (Value::String(pattern), Ok(options)) if !options.is_empty() => Value::String(format!("(?{}){}", options, pattern)),
+          (value, _) => value,
+        };
+        Query::Rx { field: field.to_owned(), value }
+      },
+      "$options" if ops.contains_key("$regex") => continue,
+      // Eq and Neq with None also match or drop explicit nulls, so $exists
+      // only has an exact meaning on _id, which every document has.
+      "$exists" if field == "_id" => match value {
+        Bson::Boolean(true) | Bson::Int32(1) | Bson::Int64(1) => Query::None,
+        Bson::Boolean(false) | Bson::Int32(0) | Bson::Int64(0) => Query::Not(Box::new(Query::None)),
+        _ => return Err(Error::UnsupportedValue { path: op_path }),
+      },
+      "$exists" => return Err(Error::UnsupportedValue { path: op_path }),
+      "$elemMatch" => match value {
+        Bson::Document(d) if d.len() == 1 && d.contains_key("$eq") => Query::Contains { field: field.to_owned(), value: value_from_bson(d.get("$eq").unwrap(), &join_path(&op_path, "$eq"))? },
+        Bson::Document(_) => return Err(Error::UnsupportedValue { path: op_path }),
+        value => Query::Contains { field: field.to_owned(), value: value_from_bson(value, &op_path)? },
+      },
+      "$not" => match value {
+        Bson::Document(d) if is_operator_document(value) => Query::Not(Box::new(field_from_bson(field, d, &op_path)?)),
+        _ => return Err(Error::UnsupportedValue { path: op_path }),
+      },
+      _ => return Err(Error::UnsupportedOperator { path: path.to_owned(), operator: op.clone() }),
+    };
+    queries.push(query);
+  }
+  Ok(Query::all(queries))
+}
+
+fn filter_from_bson(document: &Document, path: &str) -> Result<Query, Error> {
+  let mut queries = vec![];
+  for (key, value) in document {
+    let key_path = join_path(path, key);
+    let query = match key.as_str() {
+      "$and" => Query::all(documents(value, &key_path)?),
+      "$or" => Query::any(documents(value, &key_path)?),
+      "$nor" => Query::Not(Box::new(Query::any(documents(value, &key_path)?))),
+      op if op.starts_with('$') => return Err(Error::UnsupportedOperator { path: path.to_owned(), operator: op.to_owned() }),
+      field => match value {
+        Bson::Document(ops) if is_operator_document(value) => field_from_bson(field, ops, &key_path)?,
+        Bson::Document(_) => return Err(Error::UnsupportedValue { path: key_path }),
+        value => Query::Eq { field: field.to_owned(), value: value_from_bson(value, &key_path)? },
+      },
+    };
+    queries.push(query);
+  }
+  Ok(Query::all(queries))
+}
+
+pub fn from_bson(document: &Document) -> Result<Query, Error> {
+  filter_from_bson(document, "")
+}
+
 pub fn to_bson(query: &dyn ToBson) -> Document {
   query.to_bson()
 }
 
+pub fn to_pipeline(aggregate: &dyn ToPipeline) -> Vec<Document> {
+  aggregate.to_pipeline()
+}
+
+pub fn to_update(update: &dyn ToUpdate) -> Result<Document, UpdateError> {
+  update.to_update()
+}
+
+pub fn to_projection(projection: &dyn ToProjection) -> Result<Document, Error> {
+  projection.to_projection()
+}
+
+pub fn to_find_options(spec: &dyn ToFindOptions) -> Result<Document, Error> {
+  spec.to_find_options()
+}
+
 #[cfg(test)]
 mod test {
   use crate::mongo::{self, *};
@@ -68,10 +385,20 @@ mod test {
     let q3 = query!(..q.clone(); && ("a" == 5 || "b" < 5));
     let q_r = doc!("$and" : [ doc!("deleted": doc!("$eq": false)) , doc!("b": doc!("$eq": 5i64)) ]);
     let q2_r = doc!("$or" : [ doc!("$and" : [ doc!("deleted": doc!("$eq": false)) , doc!("b": doc!("$eq": 5i64)) ]) , doc!("c": doc!("$eq": 7i64)) ]);
-    let q3_r = doc!("$and" : [ doc!("$and" : [ doc!("deleted": doc!("$eq": false)) , doc!("b": doc!("$eq": 5i64)) ]) , doc!("$or" : [ doc!("a": doc!("$eq": 5i64)) , doc!("b": doc!("$lt": 5i64)) ]) ]);
+    let q3_r = doc!("$and" : [ doc!("deleted": doc!("$eq": false)) , doc!("b": doc!("$eq": 5i64)) , doc!("$or" : [ doc!("a": doc!("$eq": 5i64)) , doc!("b": doc!("$lt": 5i64)) ]) ]);
     assert_eq!(mongo::to_bson(&q), q_r);
     assert_eq!(mongo::to_bson(&q2), q2_r);
     assert_eq!(mongo::to_bson(&q3), q3_r);
+    assert_eq!(mongo::to_bson(&Query::Not(Box::new(Query::None))), doc!("_id": doc!("$exists": false)));
+    assert_eq!(mongo::to_bson(&Query::And(vec![])), doc!());
+    assert_eq!(mongo::to_bson(&Query::Or(vec![])), doc!("_id": doc!("$exists": false)));
+  }
+
+  #[test]
+  fn long_filter_bson_is_flat() {
+    let q = Query::any((0..5000).map(|i| Query::Eq { field: "id".to_owned(), value: i.into() }));
+    let ids = mongo::to_bson(&q).get_array("$or").unwrap().len();
+    assert_eq!(ids, 5000);
   }
 
   #[test]
@@ -131,4 +458,158 @@ mod test {
     let q_r = doc!("$and" : [ doc!("deleted": doc!("$eq": false)) , doc!("b": doc!("$elemMatch": 123.43f64)) ]);
     assert_eq!(mongo::to_bson(&q), q_r);
   }
+
+  #[test]
+  fn query_spec_find_options() {
+    let spec = query_spec!(("deleted" == false) order by "created_at" desc, "id" asc limit 20 offset 40);
+    assert_eq!(mongo::to_bson(&spec), doc!("deleted": doc!("$eq": false)));
+    assert_eq!(spec.to_sort(), doc!("created_at": -1, "id": 1));
+    assert_eq!(mongo::to_find_options(&spec), Ok(doc!("sort": doc!("created_at": -1, "id": 1), "limit": 20i64, "skip": 40i64)));
+    assert_eq!(mongo::to_find_options(&QuerySpec::new(Query::None)), Ok(doc!()));
+  }
+
+  #[test]
+  fn query_spec_seek() {
+    let spec = query_spec!(("deleted" == false) order by "created_at" desc, "id" asc);
+    let q_r = doc!("$and": [
+      doc!("deleted": doc!("$eq": false)),
+      doc!("$or": [
+        doc!("created_at": doc!("$lt": 100i64)),
+        doc!("$and": [ doc!("created_at": doc!("$eq": 100i64)), doc!("id": doc!("$gt": 7i64)) ]),
+      ]),
+    ]);
+    assert_eq!(mongo::to_bson(&spec.after(&[100.into(), 7.into()]).unwrap()), q_r);
+  }
+
+  #[test]
+  fn projection_document() {
+    let p = Projection::new().include("a").include("b.c").exclude("_id");
+    assert_eq!(mongo::to_projection(&p), Ok(doc!("a": 1, "b.c": 1, "_id": 0)));
+    let spec = QuerySpec::new(query!("deleted" == false)).project(p).limit(5);
+    assert_eq!(mongo::to_find_options(&spec), Ok(doc!("projection": doc!("a": 1, "b.c": 1, "_id": 0), "limit": 5i64)));
+    assert_eq!(mongo::to_projection(&Projection::new().exclude("secret")), Ok(doc!("secret": 0)));
+    let p = Projection::new().include("a").exclude("secret");
+    assert_eq!(mongo::to_projection(&p), Err(mongo::Error::MixedProjection { path: "secret".to_owned() }));
+  }
+
+  #[test]
+  fn aggregate_pipeline() {
+    let report = Aggregate::new(query!("deleted" == false))
+      .group_by("state")
+      .group_by("source.currency_iso")
+      .count("total")
+      .sum("amount", "amount")
+      .max("largest", "amount")
+      .sort(Sort::desc("total"))
+      .limit(10);
+    let pipeline = vec![
+      doc!("$match": doc!("deleted": doc!("$eq": false))),
+      doc!("$group": doc!(
+        "_id": doc!("state": "$state", "source%2Ecurrency_iso": "$source.currency_iso"),
+        "total": doc!("$sum": 1),
+        "amount": doc!("$sum": "$amount"),
+        "largest": doc!("$max": "$amount"),
+      )),
+      doc!("$project": doc!("_id": 0, "state": "$_id.state", "source.currency_iso": "$_id.source%2Ecurrency_iso", "total": 1, "amount": 1, "largest": 1)),
+      doc!("$sort": doc!("total": -1)),
+      doc!("$limit": 10i64),
+    ];
+    assert_eq!(mongo::to_pipeline(&report), pipeline);
+    let report = Aggregate::new(Query::None).group_by("a.b").group_by("a_b").count("n");
+    let pipeline = vec![
+      doc!("$group": doc!("_id": doc!("a%2Eb": "$a.b", "a_b": "$a_b"), "n": doc!("$sum": 1))),
+      doc!("$project": doc!("_id": 0, "a.b": "$_id.a%2Eb", "a_b": "$_id.a_b", "n": 1)),
+    ];
+    assert_eq!(mongo::to_pipeline(&report), pipeline);
+    let report = Aggregate::new(Query::None).avg("avg", "amount");
+    let pipeline = vec![
+      doc!("$group": doc!("_id": Null, "avg": doc!("$avg": "$amount"))),
+      doc!("$project": doc!("_id": 0, "avg": 1)),
+    ];
+    assert_eq!(mongo::to_pipeline(&report), pipeline);
+  }
+
+  #[test]
+  fn update_document() {
+    let u = update!("state" = "Done", "retries" += 1, "tags" push "late", "labels" pull "new", "owners" add_to_set 7, unset "lock", "done" = true);
+    let u_r = doc!(
+      "$set": doc!("state": "Done", "done": true),
+      "$inc": doc!("retries": 1i64),
+      "$push": doc!("tags": "late"),
+      "$pull": doc!("labels": "new"),
+      "$addToSet": doc!("owners": 7i64),
+      "$unset": doc!("lock": ""),
+    );
+    assert_eq!(mongo::to_update(&u), Ok(u_r));
+    let conflict = |field: &str, other: &str| Err(UpdateError::Conflict { field: field.to_owned(), other: other.to_owned() });
+    assert_eq!(mongo::to_update(&update!("tags" push "late", "tags" pull "new")), conflict("tags", "tags"));
+    assert_eq!(mongo::to_update(&update!("state" = "Done", "state" = "Failed")), conflict("state", "state"));
+    assert_eq!(Value::from(vec![1.5f64, 2.5f64]).to_bson_value(), Bson::Array(vec![Bson::Double(1.5), Bson::Double(2.5)]));
+  }
+
+  #[test]
+  fn from_bson_roundtrip() {
+    let q = Query::all(vec![
+      query!("deleted" == false && ("a" == 5 || "b" < 5)),
+      query!("c" in ["1", "2"]),
+      query!("d" != "x"),
+      query!("e" %% "^ab"),
+      query!("f" >= 1.5f64),
+      Query::Not(Box::new(Query::LtE { field: "g".to_owned(), value: 3.into() })),
+    ]);
+    assert_eq!(mongo::from_bson(&mongo::to_bson(&q)), Ok(q));
+  }
+
+  #[test]
+  fn from_bson_roundtrip_documents() {
+    let uuid = uuid::Uuid::new_v4();
+    let binary = || Bson::Binary(bson::Binary { subtype: BinarySubtype::Uuid, bytes: uuid.as_bytes().to_vec() });
+    let d = doc!("$and": [
+      doc!("_id": doc!("$eq": binary())),
+      doc!("owners": doc!("$in": [binary(), binary()])),
+      doc!("a": doc!("$in": [1i64, 2.5f64])),
+      doc!("b": doc!("$in": [true, false])),
+    ]);
+    let q = mongo::from_bson(&d).unwrap();
+    assert_eq!(q.to_bson_with_uuids(UuidFormat::Binary), d);
+    assert_eq!(mongo::to_bson(&q).get_array("$and").unwrap()[0], Bson::Document(doc!("_id": doc!("$eq": uuid.hyphenated().to_string()))));
+    let d = doc!("_id": doc!("$exists": false));
+    assert_eq!(mongo::to_bson(&mongo::from_bson(&d).unwrap()), d);
+    assert_eq!(mongo::from_bson(&doc!("_id": doc!("$exists": true))), Ok(Query::None));
+    let d = doc!("deleted_at": doc!("$exists": false));
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::UnsupportedValue { path: "deleted_at.$exists".to_owned() }));
+  }
+
+  #[test]
+  fn from_bson_implicit_and() {
+    let d = doc!(
+      "deleted": false,
+      "age": doc!("$gte": 21, "$lt": 65),
+      "tags": doc!("$nin": ["x", "y"]),
+      "name": doc!("$regex": "^jo", "$options": "i"),
+      "countries": doc!("$elemMatch": doc!("$eq": "za")),
+      "$nor": [ doc!("state": "Failed"), doc!("state": doc!("$not": doc!("$in": ["Done"]))) ],
+    );
+    let q_r = Query::and(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::and(Query::and(Query::GtE { field: "age".to_owned(), value: 21.into() }, Query::Lt { field: "age".to_owned(), value: 65.into() }), Query::and(Query::Not(Box::new(Query::In { field: "tags".to_owned(), value: vec!["x", "y"].into() })), Query::and(Query::Rx { field: "name".to_owned(), value: "(?i)^jo".into() }, Query::and(Query::Contains { field: "countries".to_owned(), value: "za".into() }, Query::Not(Box::new(Query::or(Query::Eq { field: "state".to_owned(), value: "Failed".into() }, Query::Not(Box::new(Query::In { field: "state".to_owned(), value: vec!["Done"].into() }))))))))));
+    assert_eq!(mongo::from_bson(&d), Ok(q_r));
+    assert_eq!(mongo::from_bson(&doc!()), Ok(Query::None));
+  }
+
+  #[test]
+  fn from_bson_errors() {
+    let d = doc!("$or": [ doc!("a": 1), doc!("b": doc!("$size": 2)) ]);
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::UnsupportedOperator { path: "$or.1.b".to_owned(), operator: "$size".to_owned() }));
+    let d = doc!("$where": "this.a == 1");
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::UnsupportedOperator { path: "".to_owned(), operator: "$where".to_owned() }));
+    let d = doc!("a": doc!("b": 1));
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::UnsupportedValue { path: "a".to_owned() }));
+    let d = doc!("$or": []);
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::EmptyArray { path: "$or".to_owned() }));
+    let d = doc!("a": 1, "$nor": []);
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::EmptyArray { path: "$nor".to_owned() }));
+    let d = doc!("$nor": [ doc!("$and": []) ]);
+    assert_eq!(mongo::from_bson(&d), Err(mongo::Error::EmptyArray { path: "$nor.0.$and".to_owned() }));
+    let q = Query::Not(Box::new(query!("a" == 1)));
+    assert_eq!(mongo::to_bson(&q), doc!("$nor": [ doc!("a": doc!("$eq": 1i64)) ]));
+  }
 }
\ No newline at end of file
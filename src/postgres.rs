@@ -1,6 +1,8 @@
 use std::fmt::Display;
 
 use crate::query::*;
+use crate::query::cursor::CursorError;
+use crate::query::update::{self, UpdateError};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Param { value: Value }
@@ -8,6 +10,9 @@ impl Param {
     pub fn from_value(v: Value) -> Param {
         Param { value: v }
     }
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
 }
 pub trait ToParam {
   fn to_param(self: Self, params: &mut Vec<Param>) -> String;
@@ -36,6 +41,9 @@ impl Where {
     fn from(wc: String, params: Vec<Param>) -> Where {
         Where { where_clause: wc, params }
     }
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
 }
 pub trait ToWhere {
     fn to_where(&self) -> Where {
@@ -44,18 +52,32 @@ pub trait ToWhere {
     fn to_where_with_params(&self, params: &mut Vec<Param>) -> Where;
 }
 
+fn join(queries: &[Query], op: &str, params: &mut Vec<Param>) -> Where {
+  let clauses = queries.iter().map(|q| q.to_where_with_params(params).to_string()).collect::<Vec<String>>();
+  Where::from(format!("({})", clauses.join(op)), params.clone())
+}
+
 impl ToWhere for Query {
   fn to_where_with_params(&self, params: &mut Vec<Param>) -> Where {
     match self {
-      Query::And { left, right } => Where::from(format!("({} AND {})", left.to_where_with_params(params), right.to_where_with_params(params)), params.clone()),
-      Query::Or { left, right } => Where::from(format!("({} OR {})", left.to_where_with_params(params), right.to_where_with_params(params)), params.clone()),
+      Query::And(queries) if queries.is_empty() => Where::from("TRUE".to_owned(), params.clone()),
+      Query::Or(queries) if queries.is_empty() => Where::from("FALSE".to_owned(), params.clone()),
+      Query::And(queries) => join(queries, " AND ", params),
+      Query::Or(queries) => join(queries, " OR ", params),
+      Query::Not(query) => match query.as_ref() {
+        Query::None => Where::from("FALSE".to_owned(), params.clone()),
+        Query::And(_) | Query::Or(_) => Where::from(format!("NOT {}", query.to_where_with_params(params)), params.clone()),
+        _ => Where::from(format!("NOT ({})", query.to_where_with_params(params)), params.clone()),
+      },
+      Query::Eq { field, value: Value::None } => Where::from(format!("{field} IS NULL", field = field), params.clone()),
+      Query::Neq { field, value: Value::None } => Where::from(format!("{field} IS NOT NULL", field = field), params.clone()),
       Query::Eq { field, value } => Where::from(format!("{field} = {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::Neq { field, value } => Where::from(format!("{field} != {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::Gt { field, value } => Where::from(format!("{field} > {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::GtE { field, value } => Where::from(format!("{field} >= {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::Lt { field, value } => Where::from(format!("{field} < {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::LtE { field, value } => Where::from(format!("{field} <= {value}", field = field, value = value.clone().to_param(params)), params.clone()),
-      Query::Rx { field, value } => Where::from(format!("{field} LIKE {value}", field = field, value = value.clone().to_param(params)), params.clone()),
+      Query::Rx { field, value } => Where::from(format!("{field} ~ {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::In { field, value } => Where::from(format!("{field} IN {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::Contains { field, value } => Where::from(format!("{field} CONTAINS {value}", field = field, value = value.clone().to_param(params)), params.clone()),
       Query::None => Where::from("".to_owned(), params.clone()),
@@ -63,10 +85,269 @@ impl ToWhere for Query {
   }
 }
 
+impl ToWhere for QuerySpec {
+  fn to_where_with_params(&self, params: &mut Vec<Param>) -> Where {
+    self.filter.to_where_with_params(params)
+  }
+}
+
+fn is_identifier(segment: &str) -> bool {
+  segment.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Sort keys and projected fields can come from request strings, so a segment
+// that is not a plain identifier is quoted instead of spliced in as is.
+fn identifier(segment: &str) -> String {
+  if is_identifier(segment) {
+    segment.to_owned()
+  } else {
+    format!("\"{}\"", segment.replace('"', "\"\""))
+  }
+}
+
+fn literal(key: &str) -> String {
+  format!("'{}'", key.replace('\'', "''"))
+}
+
+fn path(field: &str) -> String {
+  field.split('.').map(identifier).collect::<Vec<String>>().join(".")
+}
+
+pub trait ToPaging {
+  fn to_paging(&self) -> String;
+}
+
+impl ToPaging for QuerySpec {
+  fn to_paging(&self) -> String {
+    let mut clauses = vec![];
+    if !self.sort.is_empty() {
+      let order = self.sort.iter().map(|s| match s.direction {
+        Direction::Asc => format!("{} ASC", path(&s.field)),
+        Direction::Desc => format!("{} DESC", path(&s.field)),
+      }).collect::<Vec<String>>();
+      clauses.push(format!("ORDER BY {}", order.join(", ")));
+    }
+    if let Some(limit) = self.limit {
+      clauses.push(format!("LIMIT {}", limit));
+    }
+    if let Some(offset) = self.offset {
+      clauses.push(format!("OFFSET {}", offset));
+    }
+    clauses.join(" ")
+  }
+}
+
+pub trait ToSeek {
+  fn to_seek(&self, last: &[Value]) -> Result<Where, CursorError> {
+    self.to_seek_with_params(last, &mut vec![])
+  }
+  fn to_seek_with_params(&self, last: &[Value], params: &mut Vec<Param>) -> Result<Where, CursorError>;
+}
+
+impl ToSeek for QuerySpec {
+  fn to_seek_with_params(&self, last: &[Value], params: &mut Vec<Param>) -> Result<Where, CursorError> {
+    if self.sort.len() != last.len() {
+      return Err(CursorError::Length { expected: self.sort.len(), found: last.len() });
+    }
+    let keys = self.sort.iter().zip(last.iter()).collect::<Vec<(&Sort, &Value)>>();
+    let same_direction = keys.windows(2).all(|w| w[0].0.direction == w[1].0.direction);
+    if keys.len() < 2 || !same_direction {
+      return Ok(self.after(last)?.to_where_with_params(params));
+    }
+    let filter = self.filter.to_where_with_params(params);
+    let fields = keys.iter().map(|(s, _)| path(&s.field)).collect::<Vec<String>>();
+    let values = keys.iter().map(|(_, v)| (*v).clone().to_param(params)).collect::<Vec<String>>();
+    let op = match keys[0].0.direction {
+      Direction::Asc => ">",
+      Direction::Desc => "<",
+    };
+    let seek = format!("({}) {} ({})", fields.join(", "), op, values.join(", "));
+    Ok(match self.filter {
+      Query::None => Where::from(seek, params.clone()),
+      _ => Where::from(format!("({} AND {})", filter, seek), params.clone()),
+    })
+  }
+}
+
+pub trait ToColumns {
+  fn to_columns(&self) -> String;
+}
+
+fn column_expr(field: &str) -> String {
+  let mut path = field.split('.').collect::<Vec<&str>>();
+  match path.len() {
+    1 => identifier(field),
+    _ => {
+      let last = path.pop().unwrap();
+      let mut column = identifier(path[0]);
+      for p in &path[1..] {
+        column.push_str(&format!("->{}", literal(p)));
+      }
+      format!("{}->>{}", column, literal(last))
+    }
+  }
+}
+
+fn column_alias(field: &str) -> String {
+  if field.contains('.') {
+    format!("\"{}\"", field.replace('"', "\"\""))
+  } else {
+    identifier(field)
+  }
+}
+
+fn column(field: &str) -> String {
+  if field.contains('.') {
+    format!("{} AS {}", column_expr(field), column_alias(field))
+  } else {
+    identifier(field)
+  }
+}
+
+// SQL has no way to exclude columns without knowing the table, so only the
+// include list is rendered and an exclude-only projection selects everything.
+impl ToColumns for Projection {
+  fn to_columns(&self) -> String {
+    if self.include.is_empty() {
+      "*".to_owned()
+    } else {
+      self.include.iter().map(|f| column(f)).collect::<Vec<String>>().join(", ")
+    }
+  }
+}
+
+impl ToColumns for QuerySpec {
+  fn to_columns(&self) -> String {
+    self.projection.to_columns()
+  }
+}
+
+#[allow(dead_code)]
+pub struct Select {
+  sql: String,
+  params: Vec<Param>,
+}
+
+impl Display for Select {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.sql)
+  }
+}
+
+impl Select {
+  pub fn params(&self) -> &[Param] {
+    &self.params
+  }
+}
+
+pub trait ToSelect {
+  fn to_select(&self, table: &str) -> Select;
+}
+
+fn numeric(field: &str) -> String {
+  if field.contains('.') {
+    format!("({})::numeric", column_expr(field))
+  } else {
+    identifier(field)
+  }
+}
+
+impl ToSelect for Aggregate {
+  fn to_select(&self, table: &str) -> Select {
+    let mut params = vec![];
+    let mut columns = self.group_by.iter().map(|f| column(f)).collect::<Vec<String>>();
+    for (alias, accumulator) in &self.accumulators {
+      let expr = match accumulator {
+        Accumulator::Count => "COUNT(*)".to_owned(),
+        Accumulator::Sum(field) => format!("SUM({})", numeric(field)),
+        Accumulator::Avg(field) => format!("AVG({})", numeric(field)),
+        Accumulator::Min(field) => format!("MIN({})", numeric(field)),
+        Accumulator::Max(field) => format!("MAX({})", numeric(field)),
+      };
+      columns.push(format!("{} AS {}", expr, column_alias(alias)));
+    }
+    let mut sql = format!("SELECT {} FROM {}", columns.join(", "), table);
+    // Dotted filter fields go through column_expr as well, so that a field
+    // used in both WHERE and GROUP BY means the same thing in each.
+    if self.filter != Query::None {
+      let filter = self.filter.clone().map_fields(column_expr);
+      sql.push_str(&format!(" WHERE {}", filter.to_where_with_params(&mut params)));
+    }
+    if !self.group_by.is_empty() {
+      sql.push_str(&format!(" GROUP BY {}", self.group_by.iter().map(|f| column_expr(f)).collect::<Vec<String>>().join(", ")));
+    }
+    if !self.sort.is_empty() {
+      let order = self.sort.iter().map(|s| match s.direction {
+        Direction::Asc => format!("{} ASC", column_alias(&s.field)),
+        Direction::Desc => format!("{} DESC", column_alias(&s.field)),
+      }).collect::<Vec<String>>();
+      sql.push_str(&format!(" ORDER BY {}", order.join(", ")));
+    }
+    if let Some(limit) = self.limit {
+      sql.push_str(&format!(" LIMIT {}", limit));
+    }
+    Select { sql, params }
+  }
+}
+
+pub trait ToSet {
+  fn to_set(&self) -> Result<Where, UpdateError> {
+    self.to_set_with_params(&mut vec![])
+  }
+  fn to_set_with_params(&self, params: &mut Vec<Param>) -> Result<Where, UpdateError>;
+}
+
+// SET assigns whole columns, so a dotted path would be read as a table
+// qualifier; the column itself is quoted like any other identifier.
+fn set_column(field: &str) -> Result<String, UpdateError> {
+  match field.contains('.') {
+    true => Err(UpdateError::NestedField { field: field.to_owned() }),
+    false => Ok(identifier(field)),
+  }
+}
+
+impl ToSet for Update {
+  fn to_set_with_params(&self, params: &mut Vec<Param>) -> Result<Where, UpdateError> {
+    let field = set_column(self.field())?;
+    Ok(match self {
+      Update::Set { value, .. } => Where::from(format!("{field} = {value}", field = field, value = value.clone().to_param(params)), params.clone()),
+      Update::Unset { .. } => Where::from(format!("{field} = NULL", field = field), params.clone()),
+      Update::Inc { value, .. } => Where::from(format!("{field} = {field} + {value}", field = field, value = value.clone().to_param(params)), params.clone()),
+      Update::Push { value, .. } => Where::from(format!("{field} = array_append({field}, {value})", field = field, value = value.clone().to_param(params)), params.clone()),
+      Update::Pull { value, .. } => Where::from(format!("{field} = array_remove({field}, {value})", field = field, value = value.clone().to_param(params)), params.clone()),
+      Update::AddToSet { value, .. } => Where::from(format!("{field} = CASE WHEN {value} = ANY({field}) THEN {field} ELSE array_append({field}, {value}) END", field = field, value = value.clone().to_param(params)), params.clone()),
+    })
+  }
+}
+
+impl ToSet for Vec<Update> {
+  fn to_set_with_params(&self, params: &mut Vec<Param>) -> Result<Where, UpdateError> {
+    update::check(self)?;
+    let set = self.iter().map(|u| u.to_set_with_params(params).map(|w| w.to_string())).collect::<Result<Vec<String>, UpdateError>>()?;
+    Ok(Where::from(set.join(", "), params.clone()))
+  }
+}
+
 pub fn to_where(query: &dyn ToWhere) -> Where {
     query.to_where()
 }  
 
+pub fn to_set(update: &dyn ToSet) -> Result<Where, UpdateError> {
+  update.to_set()
+}
+
+pub fn to_select(aggregate: &dyn ToSelect, table: &str) -> Select {
+  aggregate.to_select(table)
+}
+
+pub fn to_columns(projection: &dyn ToColumns) -> String {
+  projection.to_columns()
+}
+
+pub fn to_paging(spec: &dyn ToPaging) -> String {
+  spec.to_paging()
+}
+
 #[cfg(test)]
 mod test {
   use crate::postgres::{self, *};
@@ -78,7 +359,7 @@ mod test {
     let q3 = query!(..q.clone(); && ("a" == 5 || "b" < 5));
     let q_r = "(deleted = $1 AND b = $2)".to_owned();
     let q2_r = format!("({} OR c = $3)", q_r);
-    let q3_r = format!("((deleted = $1 AND b = $2) AND (a = $3 OR b < $4))");
+    let q3_r = "(deleted = $1 AND b = $2 AND (a = $3 OR b < $4))";
     let result = postgres::to_where(&q);
     assert_eq!(result.where_clause, q_r);
     assert_eq!(result.params, vec![Param::from_value(Value::from(false)), Param::from_value(Value::from(5))]);
@@ -148,4 +429,92 @@ mod test {
     assert_eq!(result.where_clause, q_r);
     assert_eq!(result.params, vec![Param::from_value(Value::from(false)), Param::from_value(Value::from(123.43f64))])
   }
+
+  #[test]
+  fn query_spec_paging() {
+    let spec = query_spec!(("deleted" == false && "b" == 5) order by "created_at" desc, "id" asc limit 20 offset 40);
+    let result = postgres::to_where(&spec);
+    assert_eq!(result.where_clause, "(deleted = $1 AND b = $2)");
+    assert_eq!(postgres::to_paging(&spec), "ORDER BY created_at DESC, id ASC LIMIT 20 OFFSET 40");
+    let spec = query_spec!(("deleted" == false) limit 20);
+    assert_eq!(postgres::to_paging(&spec), "LIMIT 20");
+    assert_eq!(postgres::to_paging(&QuerySpec::new(Query::None)), "");
+    let spec = QuerySpec::new(Query::None).sort(Sort::asc("meta.created_at")).sort(Sort::desc("id; DROP TABLE t\"--"));
+    assert_eq!(postgres::to_paging(&spec), "ORDER BY meta.created_at ASC, \"id; DROP TABLE t\"\"--\" DESC");
+  }
+
+  #[test]
+  fn query_spec_seek() {
+    let spec = query_spec!(("deleted" == false) order by "created_at" desc, "id" desc limit 20);
+    let result = spec.to_seek(&[100.into(), 7.into()]).unwrap();
+    assert_eq!(result.where_clause, "(deleted = $1 AND (created_at, id) < ($2, $3))");
+    assert_eq!(result.params, vec![Param::from_value(Value::from(false)), Param::from_value(Value::from(100)), Param::from_value(Value::from(7))]);
+    let spec = query_spec!(("deleted" == false) order by "created_at" desc, "id" asc);
+    let result = spec.to_seek(&[100.into(), 7.into()]).unwrap();
+    assert_eq!(result.where_clause, "(deleted = $1 AND (created_at < $2 OR (created_at = $3 AND id > $4)))");
+    assert!(matches!(spec.to_seek(&[100.into()]), Err(CursorError::Length { expected: 2, found: 1 })));
+  }
+
+  #[test]
+  fn projection_columns() {
+    let p = Projection::new().include("id").include("source.id").include("meta.audit.user").exclude("_id");
+    assert_eq!(postgres::to_columns(&p), "id, source->>'id' AS \"source.id\", meta->'audit'->>'user' AS \"meta.audit.user\"");
+    assert_eq!(postgres::to_columns(&Projection::new().exclude("_id")), "*");
+    let p = Projection::new().include("a.b'||pg_sleep(10)||'").include("x\"y");
+    assert_eq!(postgres::to_columns(&p), "a->>'b''||pg_sleep(10)||''' AS \"a.b'||pg_sleep(10)||'\", \"x\"\"y\"");
+    let spec = QuerySpec::new(query!("deleted" == false)).project(vec!["a", "b"].into());
+    assert_eq!(postgres::to_columns(&spec), "a, b");
+  }
+
+  #[test]
+  fn aggregate_group_by() {
+    let report = Aggregate::new(query!("deleted" == false))
+      .group_by("state")
+      .group_by("source.currency_iso")
+      .count("total")
+      .sum("amount", "amount")
+      .avg("fee", "meta.fee")
+      .sort(Sort::desc("total"))
+      .limit(10);
+    let result = postgres::to_select(&report, "transfers");
+    assert_eq!(result.to_string(), "SELECT state, source->>'currency_iso' AS \"source.currency_iso\", COUNT(*) AS total, SUM(amount) AS amount, AVG((meta->>'fee')::numeric) AS fee FROM transfers WHERE deleted = $1 GROUP BY state, source->>'currency_iso' ORDER BY total DESC LIMIT 10");
+    assert_eq!(result.params(), &[Param::from_value(Value::from(false))]);
+    let report = Aggregate::new(query!("source.currency_iso" != "ZAR")).group_by("source.currency_iso").count("total");
+    assert_eq!(postgres::to_select(&report, "transfers").to_string(), "SELECT source->>'currency_iso' AS \"source.currency_iso\", COUNT(*) AS total FROM transfers WHERE source->>'currency_iso' != $1 GROUP BY source->>'currency_iso'");
+    let report = Aggregate::new(Query::None).count("total");
+    assert_eq!(postgres::to_select(&report, "transfers").to_string(), "SELECT COUNT(*) AS total FROM transfers");
+    let report = Aggregate::new(Query::None).min("low", "meta.fee").max("high", "meta.fee");
+    assert_eq!(postgres::to_select(&report, "transfers").to_string(), "SELECT MIN((meta->>'fee')::numeric) AS low, MAX((meta->>'fee')::numeric) AS high FROM transfers");
+  }
+
+  #[test]
+  fn update_set() {
+    let u = update!("state" = "Done", "retries" += 1, "tags" push "late", "owners" add_to_set "x", unset "lock");
+    let mut params = vec![];
+    let set = u.to_set_with_params(&mut params).unwrap();
+    let filter = query!("deleted" == false).to_where_with_params(&mut params);
+    assert_eq!(set.where_clause, "state = $1, retries = retries + $2, tags = array_append(tags, $3), owners = CASE WHEN $4 = ANY(owners) THEN owners ELSE array_append(owners, $4) END, lock = NULL");
+    assert_eq!(filter.where_clause, "deleted = $5");
+    assert_eq!(filter.params.len(), 5);
+    assert_eq!(postgres::to_set(&update!("tags" pull 5)).unwrap().where_clause, "tags = array_remove(tags, $1)");
+    let u = update!("tags" push "late", "tags" add_to_set "x");
+    assert_eq!(postgres::to_set(&u).map(|w| w.where_clause), Err(UpdateError::Conflict { field: "tags".to_owned(), other: "tags".to_owned() }));
+    assert_eq!(postgres::to_set(&update!("meta.state" = 1)).map(|w| w.where_clause), Err(UpdateError::NestedField { field: "meta.state".to_owned() }));
+    assert_eq!(postgres::to_set(&update!("state = 1; DROP TABLE t; --" = 1)).unwrap().where_clause, "\"state = 1; DROP TABLE t; --\" = $1");
+  }
+
+  #[test]
+  fn query_not_and_null() {
+    let q = Query::Not(Box::new(query!("deleted" == false && "b" == 5)));
+    assert_eq!(postgres::to_where(&q).where_clause, "NOT (deleted = $1 AND b = $2)");
+    let q = Query::Not(Box::new(query!("b" in [1, 2])));
+    assert_eq!(postgres::to_where(&q).where_clause, "NOT (b IN $1)");
+    assert_eq!(postgres::to_where(&query!("a" == 1 && "a" == 2).simplify()).where_clause, "FALSE");
+    let q = Query::Or(vec![Query::And(vec![]), Query::Or(vec![])]);
+    assert_eq!(postgres::to_where(&q).where_clause, "(TRUE OR FALSE)");
+    let q = Query::and(Query::Eq { field: "a".to_owned(), value: Value::None }, Query::Neq { field: "b".to_owned(), value: Value::None });
+    let result = postgres::to_where(&q);
+    assert_eq!(result.where_clause, "(a IS NULL AND b IS NOT NULL)");
+    assert!(result.params.is_empty());
+  }
 }
\ No newline at end of file
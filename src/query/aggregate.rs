@@ -0,0 +1,56 @@
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Accumulator {
+  Count,
+  Sum(String),
+  Avg(String),
+  Min(String),
+  Max(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+  pub filter: Query,
+  pub group_by: Vec<String>,
+  pub accumulators: Vec<(String, Accumulator)>,
+  pub sort: Vec<Sort>,
+  pub limit: Option<u64>,
+}
+
+impl Aggregate {
+  pub fn new(filter: Query) -> Aggregate {
+    Aggregate { filter, group_by: vec![], accumulators: vec![], sort: vec![], limit: None }
+  }
+  pub fn group_by(mut self, field: &str) -> Aggregate {
+    self.group_by.push(field.to_owned());
+    self
+  }
+  pub fn accumulate(mut self, alias: &str, accumulator: Accumulator) -> Aggregate {
+    self.accumulators.push((alias.to_owned(), accumulator));
+    self
+  }
+  pub fn count(self, alias: &str) -> Aggregate {
+    self.accumulate(alias, Accumulator::Count)
+  }
+  pub fn sum(self, alias: &str, field: &str) -> Aggregate {
+    self.accumulate(alias, Accumulator::Sum(field.to_owned()))
+  }
+  pub fn avg(self, alias: &str, field: &str) -> Aggregate {
+    self.accumulate(alias, Accumulator::Avg(field.to_owned()))
+  }
+  pub fn min(self, alias: &str, field: &str) -> Aggregate {
+    self.accumulate(alias, Accumulator::Min(field.to_owned()))
+  }
+  pub fn max(self, alias: &str, field: &str) -> Aggregate {
+    self.accumulate(alias, Accumulator::Max(field.to_owned()))
+  }
+  pub fn sort(mut self, sort: Sort) -> Aggregate {
+    self.sort.push(sort);
+    self
+  }
+  pub fn limit(mut self, limit: u64) -> Aggregate {
+    self.limit = Some(limit);
+    self
+  }
+}
@@ -0,0 +1,173 @@
+use std::ops::{BitAnd, BitOr, Not};
+
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field(String);
+
+pub fn field<S: Into<String>>(name: S) -> Field {
+  Field(name.into())
+}
+
+impl Field {
+  pub fn eq<V: Into<Value>>(self, value: V) -> Query {
+    Query::Eq { field: self.0, value: value.into() }
+  }
+  pub fn neq<V: Into<Value>>(self, value: V) -> Query {
+    Query::Neq { field: self.0, value: value.into() }
+  }
+  pub fn gt<V: Into<Value>>(self, value: V) -> Query {
+    Query::Gt { field: self.0, value: value.into() }
+  }
+  pub fn gte<V: Into<Value>>(self, value: V) -> Query {
+    Query::GtE { field: self.0, value: value.into() }
+  }
+  pub fn lt<V: Into<Value>>(self, value: V) -> Query {
+    Query::Lt { field: self.0, value: value.into() }
+  }
+  pub fn lte<V: Into<Value>>(self, value: V) -> Query {
+    Query::LtE { field: self.0, value: value.into() }
+  }
+  pub fn rx<V: Into<Value>>(self, value: V) -> Query {
+    Query::Rx { field: self.0, value: value.into() }
+  }
+  pub fn is_in<V: Into<Value>, I: IntoIterator<Item = V>>(self, values: I) -> Query {
+    let values = values.into_iter().map(|v| v.into()).collect::<Vec<Value>>();
+    Query::In { field: self.0, value: Value::Array(Box::new(values)) }
+  }
+  pub fn contains<V: Into<Value>>(self, value: V) -> Query {
+    Query::Contains { field: self.0, value: value.into() }
+  }
+  pub fn is_null(self) -> Query {
+    Query::Eq { field: self.0, value: Value::None }
+  }
+  pub fn is_not_null(self) -> Query {
+    Query::Neq { field: self.0, value: Value::None }
+  }
+}
+
+impl BitAnd for Query {
+  type Output = Query;
+  fn bitand(self, other: Query) -> Query {
+    self.and(other)
+  }
+}
+
+impl BitOr for Query {
+  type Output = Query;
+  fn bitor(self, other: Query) -> Query {
+    self.or(other)
+  }
+}
+
+impl Not for Query {
+  type Output = Query;
+  fn not(self) -> Query {
+    Query::Not(Box::new(self))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::builder::*;
+  use crate::query;
+
+  #[test]
+  fn builder_composition() {
+    let q = field("deleted").eq(false).and(field("b").eq(5));
+    assert_eq!(q, query!("deleted" == false && "b" == 5));
+    assert_eq!(q.clone().or(field("c").eq(7)), query!(..q.clone(); || "c" == 7));
+    assert_eq!(q.clone() & (field("a").eq(5) | field("b").lt(5)), query!(..q.clone(); && ("a" == 5 || "b" < 5)));
+  }
+
+  #[test]
+  fn builder_operators() {
+    assert_eq!(field("a").neq(1), query!("a" != 1));
+    assert_eq!(field("a").gt(1), query!("a" > 1));
+    assert_eq!(field("a").gte(1), query!("a" >= 1));
+    assert_eq!(field("a").lt(1), query!("a" < 1));
+    assert_eq!(field("a").lte(1), query!("a" <= 1));
+    assert_eq!(field("a").lte(1), Query::LtE { field: "a".to_owned(), value: 1.into() });
+    assert_eq!(field("a").rx("^jo"), query!("a" %% "^jo"));
+    assert_eq!(field("a").is_null(), Query::Eq { field: "a".to_owned(), value: Value::None });
+    assert_eq!(!field("a").is_not_null(), Query::Not(Box::new(Query::Neq { field: "a".to_owned(), value: Value::None })));
+  }
+
+  #[test]
+  fn builder_in() {
+    let q = field("_id").is_in(vec!["123", "456", "789"]) & field("deleted").eq(false);
+    assert_eq!(q, query!("_id" in ["123", "456","789"] && "deleted" == false));
+    let q = field("_id").is_in(vec![123, 456, 789]) & field("deleted").eq(false);
+    assert_eq!(q, query!("_id" in [123, 456,789] && "deleted" == false));
+  }
+
+  #[test]
+  fn builder_contains() {
+    let q = field("deleted").eq(false) & field("countries").contains("za");
+    assert_eq!(q, query!("deleted" == false && "countries" contains "za"));
+  }
+
+  #[test]
+  fn builder_optional() {
+    let countries = || field("countries").contains("za");
+    let q = field("deleted").eq(false).and_if(1 == 0, countries());
+    assert_eq!(q, query!("deleted" == false && cond(1==0) "countries" contains "za"));
+    let q = field("deleted").eq(false).and_if(1 == 1, countries());
+    assert_eq!(q, query!("deleted" == false && cond(1==1) "countries" contains "za"));
+    let q = field("deleted").eq(false).and_if(1 == 1, countries().or_if(1 == 0, field("age").gte(21)));
+    assert_eq!(q, query!("deleted" == false && cond(1==1) "countries" contains "za" || cond(1==0) "age" >= 21));
+    let q = field("deleted").eq(false).and_if(1 == 1, countries().or_if(1 == 1, field("age").gte(21)));
+    assert_eq!(q, query!("deleted" == false && cond(1==1) "countries" contains "za" || cond(1==1) "age" >= 21));
+    let q = field("deleted").eq(false).and_if(1 == 0, countries().or_if(1 == 1, field("age").gte(21)));
+    assert_eq!(q, query!("deleted" == false && cond(1==0) "countries" contains "za" || cond(1==1) "age" >= 21));
+    let q = field("deleted").eq(false).and_if(1 == 0, countries()).or_if(1 == 1, field("age").gte(21));
+    assert_eq!(q, query!(("deleted" == false && cond(1==0) "countries" contains "za") || cond(1==1) "age" >= 21));
+  }
+
+  fn complex_macro(entity: Option<i32>, state: Option<&str>, currency_iso: Option<&str>) -> Query {
+    query!(
+      (
+        ("deleted" == false && cond(entity.is_some()) ("source.id" == { entity.unwrap() } || "destination.id" == { entity.unwrap() } )) &&
+        cond(state.is_some()) "state" == { state.unwrap() }
+      ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.unwrap() } || ("destination.currency_iso" == { currency_iso.unwrap() }))
+    )
+  }
+
+  fn complex_builder(entity: Option<i32>, state: Option<&str>, currency_iso: Option<&str>) -> Query {
+    let mut q = field("deleted").eq(false);
+    if let Some(entity) = entity {
+      q = q & (field("source.id").eq(entity) | field("destination.id").eq(entity));
+    }
+    if let Some(state) = state {
+      q = q & field("state").eq(state);
+    }
+    if let Some(currency_iso) = currency_iso {
+      q = q & (field("source.currency_iso").eq(currency_iso) | field("destination.currency_iso").eq(currency_iso));
+    }
+    q
+  }
+
+  #[test]
+  fn builder_complex_optional() {
+    for entity in [Some(12), None] {
+      for state in [Some("Pending"), None] {
+        for currency_iso in [Some("USD"), None] {
+          assert_eq!(complex_builder(entity, state, currency_iso), complex_macro(entity, state, currency_iso));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn builder_in_loops() {
+    let q = (0..3).fold(!Query::None, |q, i| q | field("id").eq(i));
+    assert_eq!(q, query!("id" == 0 || "id" == 1 || "id" == 2));
+    assert_eq!(field("a").eq(1) | Query::None, Query::None);
+    assert_eq!(field("a").eq(1) & !Query::None, !Query::None);
+    let q = Query::all(vec![("a", 1), ("b", 2)].into_iter().map(|(f, v)| field(f).eq(v)));
+    assert_eq!(q, query!("a" == 1 && "b" == 2));
+    assert_eq!(Query::None & field("a").eq(1), field("a").eq(1));
+    assert_eq!(Query::all(vec![]), Query::None);
+    assert_eq!(Query::any(vec![]), !Query::None);
+  }
+}
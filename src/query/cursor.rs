@@ -0,0 +1,245 @@
+use std::fmt;
+
+use crate::query::*;
+
+impl Query {
+  // Builds the keyset predicate that selects the rows strictly after `last`
+  // for the given sort order: a == v1 && b > v2 style cascades OR'ed together.
+  // `last` needs one value per sort key; a cursor taken under a different
+  // sort would otherwise quietly seek on the wrong columns.
+  pub fn seek(sort: &[Sort], last: &[Value]) -> Result<Query, CursorError> {
+    if sort.len() != last.len() {
+      return Err(CursorError::Length { expected: sort.len(), found: last.len() });
+    }
+    let keys = sort.iter().zip(last.iter()).collect::<Vec<(&Sort, &Value)>>();
+    let mut terms = vec![];
+    for i in 0..keys.len() {
+      let (s, v) = keys[i];
+      let mut term = keys[..i].iter().map(|(prev, pv)| Query::Eq { field: prev.field.clone(), value: (*pv).clone() }).collect::<Vec<Query>>();
+      term.push(match s.direction {
+        Direction::Asc => Query::Gt { field: s.field.clone(), value: v.clone() },
+        Direction::Desc => Query::Lt { field: s.field.clone(), value: v.clone() },
+      });
+      terms.push(Query::all(term));
+    }
+    Ok(match terms.len() {
+      0 => Query::None,
+      _ => Query::any(terms),
+    })
+  }
+}
+
+impl QuerySpec {
+  pub fn after(&self, last: &[Value]) -> Result<QuerySpec, CursorError> {
+    let seek = Query::seek(&self.sort, last)?;
+    let filter = match (&self.filter, seek) {
+      (_, Query::None) => self.filter.clone(),
+      (Query::None, seek) => seek,
+      (filter, seek) => Query::and(filter.clone(), seek),
+    };
+    Ok(QuerySpec { filter, sort: self.sort.clone(), projection: self.projection.clone(), limit: self.limit, offset: None })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorError {
+  Encoding,
+  Signature,
+  Malformed,
+  Length { expected: usize, found: usize },
+}
+
+impl fmt::Display for CursorError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CursorError::Encoding => write!(f, "cursor is not valid base64"),
+      CursorError::Signature => write!(f, "cursor signature does not match"),
+      CursorError::Malformed => write!(f, "cursor payload is malformed"),
+      CursorError::Length { expected, found } => write!(f, "cursor has {} values for {} sort keys", found, expected),
+    }
+  }
+}
+
+impl std::error::Error for CursorError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+  pub values: Vec<Value>,
+}
+
+impl Cursor {
+  pub fn new(values: Vec<Value>) -> Cursor {
+    Cursor { values }
+  }
+}
+
+#[cfg(feature = "cursor")]
+mod encoding {
+  use base64::Engine;
+  use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+  use hmac::{Hmac, Mac};
+  use sha2::Sha256;
+  use uuid::Uuid;
+
+  use super::{Cursor, CursorError};
+  use crate::query::Value;
+
+  type HmacSha256 = Hmac<Sha256>;
+  const SIGNATURE_LEN: usize = 32;
+
+  fn write_value(value: &Value, out: &mut Vec<u8>) {
+    match value {
+      Value::Uuid(u) => {
+        out.push(b'u');
+        out.extend_from_slice(u.as_bytes());
+      },
+      Value::Number(n) => {
+        out.push(b'n');
+        out.extend_from_slice(&n.to_be_bytes());
+      },
+      Value::Float(f) => {
+        out.push(b'f');
+        out.extend_from_slice(&f.to_bits().to_be_bytes());
+      },
+      Value::String(s) => {
+        out.push(b's');
+        out.extend_from_slice(&(s.len() as u32).to_be_bytes());
+        out.extend_from_slice(s.as_bytes());
+      },
+      Value::Bool(b) => {
+        out.push(b'b');
+        out.push(*b as u8);
+      },
+      Value::Array(arr) => {
+        out.push(b'a');
+        out.extend_from_slice(&(arr.len() as u32).to_be_bytes());
+        for v in arr.iter() {
+          write_value(v, out);
+        }
+      },
+      Value::None => out.push(b'0'),
+    }
+  }
+
+  fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], CursorError> {
+    if input.len() < n {
+      return Err(CursorError::Malformed);
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+  }
+
+  fn take_u32(input: &mut &[u8]) -> Result<usize, CursorError> {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(take(input, 4)?);
+    Ok(u32::from_be_bytes(buf) as usize)
+  }
+
+  fn take_u64(input: &mut &[u8]) -> Result<u64, CursorError> {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(take(input, 8)?);
+    Ok(u64::from_be_bytes(buf))
+  }
+
+  fn read_value(input: &mut &[u8]) -> Result<Value, CursorError> {
+    match take(input, 1)?[0] {
+      b'u' => Uuid::from_slice(take(input, 16)?).map(Value::Uuid).map_err(|_| CursorError::Malformed),
+      b'n' => Ok(Value::Number(take_u64(input)? as i64)),
+      b'f' => Ok(Value::Float(f64::from_bits(take_u64(input)?))),
+      b's' => {
+        let len = take_u32(input)?;
+        String::from_utf8(take(input, len)?.to_vec()).map(Value::String).map_err(|_| CursorError::Malformed)
+      },
+      b'b' => Ok(Value::Bool(take(input, 1)?[0] != 0)),
+      b'a' => {
+        let len = take_u32(input)?;
+        let mut arr = vec![];
+        for _ in 0..len {
+          arr.push(read_value(input)?);
+        }
+        Ok(Value::Array(Box::new(arr)))
+      },
+      b'0' => Ok(Value::None),
+      _ => Err(CursorError::Malformed),
+    }
+  }
+
+  fn mac(secret: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length")
+  }
+
+  impl Cursor {
+    pub fn encode(&self, secret: &[u8]) -> String {
+      let mut payload = vec![];
+      for v in &self.values {
+        write_value(v, &mut payload);
+      }
+      let mut mac = mac(secret);
+      mac.update(&payload);
+      payload.extend_from_slice(&mac.finalize().into_bytes());
+      URL_SAFE_NO_PAD.encode(payload)
+    }
+
+    pub fn decode(cursor: &str, secret: &[u8]) -> Result<Cursor, CursorError> {
+      let bytes = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| CursorError::Encoding)?;
+      if bytes.len() < SIGNATURE_LEN {
+        return Err(CursorError::Malformed);
+      }
+      let (payload, signature) = bytes.split_at(bytes.len() - SIGNATURE_LEN);
+      let mut mac = mac(secret);
+      mac.update(payload);
+      mac.verify_slice(signature).map_err(|_| CursorError::Signature)?;
+      let mut input = payload;
+      let mut values = vec![];
+      while !input.is_empty() {
+        values.push(read_value(&mut input)?);
+      }
+      Ok(Cursor { values })
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::cursor::*;
+  use crate::{query, query_spec};
+
+  #[test]
+  fn seek_cascade() {
+    let sort = vec![Sort::desc("created_at"), Sort::asc("id")];
+    let q = Query::seek(&sort, &[Value::from(100), Value::from("abc")]).unwrap();
+    let q_r = Query::Or(vec![
+      Query::Lt { field: "created_at".to_owned(), value: 100.into() },
+      Query::And(vec![
+        Query::Eq { field: "created_at".to_owned(), value: 100.into() },
+        Query::Gt { field: "id".to_owned(), value: "abc".into() },
+      ]),
+    ]);
+    assert_eq!(q, q_r);
+    assert_eq!(Query::seek(&[], &[]), Ok(Query::None));
+    assert_eq!(Query::seek(&sort, &[]), Err(CursorError::Length { expected: 2, found: 0 }));
+    assert_eq!(Query::seek(&sort[..1], &[Value::from(100), Value::from("abc")]), Err(CursorError::Length { expected: 1, found: 2 }));
+  }
+
+  #[test]
+  fn spec_after() {
+    let spec = query_spec!(("deleted" == false) order by "id" asc limit 20 offset 40);
+    let q_r = QuerySpec::new(query!("deleted" == false && "id" > 7)).sort(Sort::asc("id")).limit(20);
+    assert_eq!(spec.after(&[7.into()]), Ok(q_r));
+  }
+
+  #[cfg(feature = "cursor")]
+  #[test]
+  fn cursor_roundtrip() {
+    let uuid = uuid::Uuid::new_v4();
+    let cursor = Cursor::new(vec![1234.into(), "a'b".into(), 1.5f64.into(), uuid.into(), vec![1i64, 2].into(), true.into(), Value::None]);
+    let encoded = cursor.encode(b"secret");
+    assert_eq!(Cursor::decode(&encoded, b"secret"), Ok(cursor));
+    assert_eq!(Cursor::decode(&encoded, b"other"), Err(CursorError::Signature));
+    assert_eq!(Cursor::decode("not base64!", b"secret"), Err(CursorError::Encoding));
+    let mut tampered = encoded.into_bytes();
+    tampered[2] = if tampered[2] == b'A' { b'B' } else { b'A' };
+    assert_eq!(Cursor::decode(std::str::from_utf8(&tampered).unwrap(), b"secret"), Err(CursorError::Signature));
+  }
+}
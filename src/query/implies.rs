@@ -0,0 +1,200 @@
+use std::cmp::Ordering::{self, *};
+
+use crate::query::*;
+use crate::query::normal::clauses;
+use crate::query::simplify::{compare, equal, member, satisfies};
+
+// Past this many branches or clauses the check gives up and answers false,
+// which is always a safe answer.
+const MAX_CLAUSES: usize = 256;
+
+fn is(a: &Value, b: &Value, orderings: &[Ordering]) -> bool {
+  compare(a, b).is_some_and(|o| orderings.contains(&o))
+}
+
+fn members(value: &Value) -> &[Value] {
+  match value {
+    Value::Array(values) => values.as_slice(),
+    value => std::slice::from_ref(value),
+  }
+}
+
+fn field(query: &Query) -> Option<&String> {
+  match query {
+    Query::Not(query) => query.predicate().map(|(field, _)| field),
+    query => query.predicate().map(|(field, _)| field),
+  }
+}
+
+// On a document that has the field, an ordering comparison fails exactly
+// when its dual holds. Without the field both fail, so the dual alone does
+// not stand in for the negation.
+fn dual(query: &Query) -> Option<Query> {
+  match query.clone() {
+    Query::Gt { field, value } => Some(Query::LtE { field, value }),
+    Query::GtE { field, value } => Some(Query::Lt { field, value }),
+    Query::Lt { field, value } => Some(Query::GtE { field, value }),
+    Query::LtE { field, value } => Some(Query::Gt { field, value }),
+    _ => None,
+  }
+}
+
+// Whether `query` only matches documents that have the field.
+fn present(query: &Query) -> bool {
+  match query {
+    Query::Eq { value, .. } => *value != Value::None,
+    Query::Gt { .. } | Query::GtE { .. } | Query::Lt { .. } | Query::LtE { .. } => true,
+    _ => false,
+  }
+}
+
+// Whether every document matched by the literal `p` is also matched by `q`.
+fn literal_implies(p: &Query, q: &Query) -> bool {
+  if p == q {
+    return true;
+  }
+  let field = match (field(p), field(q)) {
+    (Some(a), Some(b)) if a == b => a,
+    _ => return false,
+  };
+  let eq = |value: &Value| Query::Eq { field: field.clone(), value: value.clone() };
+  let neq = |value: &Value| Query::Neq { field: field.clone(), value: value.clone() };
+  match (p, q) {
+    (Query::In { value, .. }, _) => members(value).iter().all(|v| literal_implies(&eq(v), q)),
+    (_, Query::Not(inner)) => match (p, inner.as_ref()) {
+      (_, Query::In { value, .. }) => members(value).iter().all(|w| literal_implies(p, &neq(w))),
+      (Query::Not(outer), inner) => literal_implies(inner, outer),
+      (p, inner) if present(p) => dual(inner).is_some_and(|dual| literal_implies(p, &dual)),
+      _ => false,
+    },
+    (Query::Eq { value: v, .. }, Query::Eq { value: w, .. }) => equal(v, w) == Some(true),
+    (Query::Eq { value: v, .. }, Query::Neq { value: w, .. }) => equal(v, w) == Some(false),
+    (Query::Eq { value: v, .. }, Query::In { value: w, .. }) => member(v, members(w)) == Some(true),
+    (Query::Eq { value: v, .. }, bound) => satisfies(v, bound) == Some(true),
+    (Query::Gt { value: v, .. }, Query::Gt { value: w, .. }) | (Query::Gt { value: v, .. }, Query::GtE { value: w, .. })
+      | (Query::GtE { value: v, .. }, Query::GtE { value: w, .. }) => is(v, w, &[Greater, Equal]),
+    (Query::GtE { value: v, .. }, Query::Gt { value: w, .. }) => is(v, w, &[Greater]),
+    (Query::Lt { value: v, .. }, Query::Lt { value: w, .. }) | (Query::Lt { value: v, .. }, Query::LtE { value: w, .. })
+      | (Query::LtE { value: v, .. }, Query::LtE { value: w, .. }) => is(v, w, &[Less, Equal]),
+    (Query::LtE { value: v, .. }, Query::Lt { value: w, .. }) => is(v, w, &[Less]),
+    (Query::Gt { value: v, .. }, Query::Neq { value: w, .. }) => is(w, v, &[Less, Equal]),
+    (Query::GtE { value: v, .. }, Query::Neq { value: w, .. }) => is(w, v, &[Less]),
+    (Query::Lt { value: v, .. }, Query::Neq { value: w, .. }) => is(w, v, &[Greater, Equal]),
+    (Query::LtE { value: v, .. }, Query::Neq { value: w, .. }) => is(w, v, &[Greater]),
+    (Query::Neq { value: v, .. }, Query::Neq { value: w, .. }) => is(v, w, &[Equal]),
+    (Query::Not(inner), Query::Neq { value: w, .. }) => match inner.as_ref() {
+      Query::In { value, .. } => member(w, members(value)) == Some(true),
+      _ => false,
+    },
+    _ => false,
+  }
+}
+
+// An In literal is a disjunction of equalities, so each of its members may
+// be covered by a different literal of the clause.
+fn clause_implied(p: &Query, clause: &[Query]) -> bool {
+  match p {
+    Query::In { field, value } => members(value).iter().all(|v| {
+      clause_implied(&Query::Eq { field: field.clone(), value: v.clone() }, clause)
+    }),
+    _ => clause.iter().any(|q| literal_implies(p, q)),
+  }
+}
+
+fn literals(query: Query) -> Vec<Query> {
+  match query {
+    Query::And(queries) => queries,
+    Query::None => vec![],
+    query => vec![query],
+  }
+}
+
+impl Query {
+  // Sound but incomplete: true means every document matched by `self` is
+  // matched by `other`, false only means that could not be shown. Each DNF
+  // branch of `self` has to imply every CNF clause of `other`.
+  pub fn implies(&self, other: &Query) -> bool {
+    let branches = match clauses(self.clone().to_nnf(), false, MAX_CLAUSES) {
+      Ok(branches) => branches,
+      Err(_) => return false,
+    };
+    let clauses = match clauses(other.clone().to_nnf(), true, MAX_CLAUSES) {
+      Ok(clauses) => clauses,
+      Err(_) => return false,
+    };
+    branches.into_iter().all(|branch| {
+      let branch = Query::all(branch).simplify();
+      if branch.is_false() {
+        return true;
+      }
+      let literals = literals(branch);
+      clauses.iter().all(|clause| literals.iter().any(|p| clause_implied(p, clause)))
+    })
+  }
+  pub fn equivalent(&self, other: &Query) -> bool {
+    self.implies(other) && other.implies(self)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::implies::*;
+  use crate::query;
+
+  fn is_in(field: &str, value: Value) -> Query {
+    Query::In { field: field.to_owned(), value }
+  }
+
+  #[test]
+  fn implies_corpus() {
+    let corpus = vec![
+      // ranges and equality
+      (query!("a" > 5 && "b" == 1), query!("a" > 3), true),
+      (query!("a" > 3), query!("a" > 5), false),
+      (query!("a" >= 5), query!("a" > 5), false),
+      (query!("a" > 5), query!("a" >= 5), true),
+      (query!("a" == 4), query!("a" > 3 && "a" < 5), true),
+      (query!("a" < 2), query!("a" <= 2), true),
+      (query!("a" == 4), query!("a" != 5), true),
+      (query!("a" > 5), query!("a" != 5), true),
+      (query!("a" >= 5), query!("a" != 5), false),
+      (query!("a" == 4), query!("a" == 4.0f64), true),
+      (query!("a" == "x"), query!("a" > 3), false),
+      (query!("a" > "b"), query!("a" > "a"), false),
+      (query!("a" == "x"), query!("a" != "y"), true),
+      (query!("a" == 9007199254740993i64), query!("a" == 9007199254740992.0f64), false),
+      // In sets
+      (query!("a" == 2), is_in("a", vec![1, 2, 3].into()), true),
+      (is_in("a", vec![1, 2].into()), is_in("a", vec![1, 2, 3].into()), true),
+      (is_in("a", vec![1, 4].into()), is_in("a", vec![1, 2, 3].into()), false),
+      (is_in("a", vec![6, 7].into()), query!("a" > 5), true),
+      (query!("a" > 5), !is_in("a", vec![1, 5].into()), true),
+      (!is_in("a", vec![1, 2, 3].into()), query!("a" != 2), true),
+      // boolean structure
+      (query!("a" == 1 || "a" == 2), is_in("a", vec![1, 2].into()), true),
+      (query!("a" == 1 || "b" == 2), query!("a" == 1), false),
+      (query!("a" == 1), query!("a" == 1 || "b" == 2), true),
+      (query!("a" > 5 && ("b" == 1 || "b" == 2)), query!("a" > 0 && "b" < 3), true),
+      // negated ordering also matches documents without the field
+      (!Query::or(query!("a" <= 5), query!("b" == 1)), query!("a" > 5 && "b" != 1), false),
+      (query!("a" > 5 && "b" == 2), !Query::or(query!("a" <= 5), query!("b" == 1)), true),
+      (!query!("a" > 5), !query!("a" > 7), true),
+      (!query!("a" > 7), !query!("a" > 5), false),
+      (query!("a" == 1 && "a" == 2), query!("z" == 9), true),
+      (query!("z" == 9), Query::None, true),
+      (Query::None, query!("z" == 9), false),
+    ];
+    for (a, b, expected) in corpus {
+      assert_eq!(a.implies(&b), expected, "{:?} implies {:?}", a, b);
+    }
+  }
+
+  #[test]
+  fn equivalence() {
+    assert!(query!("a" == 1 || "a" == 2).equivalent(&is_in("a", vec![2, 1].into())));
+    assert!((!query!("a" > 5 || "b" == 1)).equivalent(&Query::and(!query!("a" > 5), query!("b" != 1))));
+    assert!(!(!query!("a" > 5)).equivalent(&query!("a" <= 5)));
+    assert!(query!("a" == 1 && "b" == 2).equivalent(&query!("b" == 2 && "a" == 1)));
+    assert!(!query!("a" > 5).equivalent(&query!("a" >= 5)));
+  }
+}
@@ -1,6 +1,27 @@
 use uuid::Uuid;
 
 #[cfg(feature = "parse")] pub mod parse;
+pub mod cursor;
+pub mod projection;
+pub mod aggregate;
+#[macro_use] pub mod update;
+pub mod visit;
+pub mod simplify;
+pub mod normal;
+pub mod implies;
+pub mod builder;
+pub mod typed;
+pub mod schema;
+pub mod policy;
+
+pub use self::projection::Projection;
+pub use self::aggregate::{Aggregate, Accumulator};
+pub use self::update::Update;
+pub use self::visit::{QueryVisitor, QueryFolder};
+pub use self::builder::{field, Field};
+pub use self::typed::{Numeric, Queryable, TypedField};
+pub use self::schema::{FieldSchema, Operator, Schema, ValidationError, ValueType};
+pub use self::policy::{Policy, PolicyError};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
@@ -85,6 +106,21 @@ impl From<i64> for Value {
   }
 }
 
+// Types that widen to i64 or f64 without loss. u64 and wider do not, so the
+// derive refuses fields of those types.
+macro_rules! from_widened_value {
+  ($variant:ident, $target:ident, $($t:ident),+) => {
+    $(impl From<$t> for Value {
+      fn from(v: $t) -> Value {
+        Value::$variant($target::from(v))
+      }
+    })+
+  };
+}
+
+from_widened_value!(Number, i64, i8, i16, u8, u16, u32);
+from_widened_value!(Float, f64, f32);
+
 impl From<f64> for Value {
   fn from(v: f64) -> Value {
     Value::Float(v)
@@ -112,8 +148,9 @@ impl From<bool> for Value {
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Query {
-  Or { left: Box<Query>, right: Box<Query> },
-  And { left: Box<Query>, right: Box<Query> },
+  Or(Vec<Query>),
+  And(Vec<Query>),
+  Not(Box<Query>),
   Eq { field: String, value: Value },
   Neq { field: String, value: Value },
   Gt { field: String, value: Value },
@@ -126,13 +163,209 @@ pub enum Query {
   None,
 }
 
+// And and Or used to be binary `{ left, right }` nodes (see CHANGELOG.md for
+// migrating); Query::and(left, right) and Query::or(left, right) build the
+// same filters and flatten as they go.
+// Query::None (true) and its negation (false) are dropped where they are the
+// identity and absorb everything where they dominate, so a filter can be
+// grown in a loop starting from either constant.
+impl Query {
+  pub fn and(self, other: Query) -> Query {
+    Query::all(vec![self, other])
+  }
+  pub fn or(self, other: Query) -> Query {
+    Query::any(vec![self, other])
+  }
+  pub fn and_if(self, condition: bool, other: Query) -> Query {
+    if condition { self.and(other) } else { self }
+  }
+  pub fn or_if(self, condition: bool, other: Query) -> Query {
+    if condition { self.or(other) } else { self }
+  }
+  pub fn all<I: IntoIterator<Item = Query>>(queries: I) -> Query {
+    let mut all = vec![];
+    for query in queries {
+      match query {
+        Query::And(queries) => all.extend(queries),
+        Query::None => {},
+        query if query.is_false() => return query,
+        query => all.push(query),
+      }
+    }
+    match all.len() {
+      0 => Query::None,
+      1 => all.pop().unwrap(),
+      _ => Query::And(all),
+    }
+  }
+  pub fn any<I: IntoIterator<Item = Query>>(queries: I) -> Query {
+    let mut any = vec![];
+    for query in queries {
+      match query {
+        Query::Or(queries) => any.extend(queries),
+        Query::None => return Query::None,
+        query if query.is_false() => {},
+        query => any.push(query),
+      }
+    }
+    match any.len() {
+      0 => Query::Not(Box::new(Query::None)),
+      1 => any.pop().unwrap(),
+      _ => Query::Or(any),
+    }
+  }
+  pub fn operator(&self) -> &'static str {
+    match self {
+      Query::Or(_) => "$or",
+      Query::And(_) => "$and",
+      Query::Not(_) => "$not",
+      Query::Eq { .. } => "$eq",
+      Query::Neq { .. } => "$ne",
+      Query::Gt { .. } => "$gt",
+      Query::GtE { .. } => "$gte",
+      Query::Lt { .. } => "$lt",
+      Query::LtE { .. } => "$lte",
+      Query::Rx { .. } => "$regex",
+      Query::In { .. } => "$in",
+      Query::Contains { .. } => "$elemMatch",
+      Query::None => "",
+    }
+  }
+}
+
+// Collects the operands of nested Ands (or Ors) into one list, for simplify
+// and for backends whose syntax reads better without the extra grouping.
+pub(crate) fn flatten<'a>(query: &'a Query, and: bool, out: &mut Vec<&'a Query>) {
+  match (query, and) {
+    (Query::And(queries), true) | (Query::Or(queries), false) => {
+      for query in queries {
+        flatten(query, and, out);
+      }
+    },
+    _ => out.push(query),
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+  Asc,
+  Desc,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+  pub field: String,
+  pub direction: Direction,
+}
+
+impl Sort {
+  pub fn asc(field: &str) -> Sort {
+    Sort { field: field.to_owned(), direction: Direction::Asc }
+  }
+  pub fn desc(field: &str) -> Sort {
+    Sort { field: field.to_owned(), direction: Direction::Desc }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuerySpec {
+  pub filter: Query,
+  pub sort: Vec<Sort>,
+  pub projection: Projection,
+  pub limit: Option<u64>,
+  pub offset: Option<u64>,
+}
+
+impl QuerySpec {
+  pub fn new(filter: Query) -> QuerySpec {
+    QuerySpec { filter, sort: vec![], projection: Projection::new(), limit: None, offset: None }
+  }
+  pub fn sort(mut self, sort: Sort) -> QuerySpec {
+    self.sort.push(sort);
+    self
+  }
+  pub fn project(mut self, projection: Projection) -> QuerySpec {
+    self.projection = projection;
+    self
+  }
+  pub fn limit(mut self, limit: u64) -> QuerySpec {
+    self.limit = Some(limit);
+    self
+  }
+  pub fn offset(mut self, offset: u64) -> QuerySpec {
+    self.offset = Some(offset);
+    self
+  }
+}
+
+impl From<Query> for QuerySpec {
+  fn from(filter: Query) -> QuerySpec {
+    QuerySpec::new(filter)
+  }
+}
+
+// Splits a regex on the `|` that are not inside a group or a class.
+#[cfg(any(feature = "cypher", feature = "tantivy", feature = "elastic"))]
+fn alternatives(pattern: &str) -> Vec<&str> {
+  let mut out = vec![];
+  let (mut depth, mut class, mut escaped, mut start) = (0, false, false, 0);
+  for (i, c) in pattern.char_indices() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' => escaped = true,
+      '[' => class = true,
+      ']' => class = false,
+      '(' if !class => depth += 1,
+      ')' if !class => depth -= 1,
+      '|' if !class && depth == 0 => {
+        out.push(&pattern[start..i]);
+        start = i + 1;
+      },
+      _ => {},
+    }
+  }
+  out.push(&pattern[start..]);
+  out
+}
+
+// Rewrites a regex that only has to match somewhere in the value into one
+// that has to match all of it, for engines that always match the whole
+// string. Every alternative carries its own anchors and is grouped before it
+// is padded, so `^a|b$` keeps meaning "starts with a or ends with b".
+#[cfg(any(feature = "cypher", feature = "tantivy"))]
+pub(crate) fn anchored(pattern: &str) -> String {
+  anchored_with(pattern, "(?:")
+}
+
+// `open` starts each group, for syntaxes such as Lucene's that have no
+// non-capturing groups.
+#[cfg(any(feature = "cypher", feature = "tantivy", feature = "elastic"))]
+pub(crate) fn anchored_with(pattern: &str, open: &str) -> String {
+  let alternatives = alternatives(pattern).into_iter().map(|alternative| {
+    let (start, body) = match alternative.strip_prefix('^') {
+      Some(rest) => ("", rest),
+      None => (".*", alternative),
+    };
+    // A `$` after an odd number of backslashes is a literal dollar.
+    let (body, end) = match body.strip_suffix('$') {
+      Some(rest) if (rest.len() - rest.trim_end_matches('\\').len()) % 2 == 0 => (rest, ""),
+      _ => (body, ".*"),
+    };
+    format!("{}{}{}){}", start, open, body, end)
+  }).collect::<Vec<String>>();
+  match alternatives.len() {
+    1 => alternatives.concat(),
+    _ => format!("{}{})", open, alternatives.join("|")),
+  }
+}
+
 #[allow(unused_macros)]
 #[macro_export]
 macro_rules! query {
-  ( ..$lhs:tt && $($rest:tt)+ ) => {{ Query::And { left: Box::new($lhs), right: Box::new(query!($($rest)*)) } }};
-  ( ..$lhs:expr; && $($rest:tt)+ ) => {{ Query::And { left: Box::new($lhs), right: Box::new(query!($($rest)*)) } }};
-  ( ..$lhs:tt || $($rest:tt)+ ) => {{ Query::Or { left: Box::new($lhs), right: Box::new(query!($($rest)*)) } }};
-  ( ..$lhs:expr; || $($rest:tt)+ ) => {{ Query::Or { left: Box::new($lhs), right: Box::new(query!($($rest)*)) } }};
+  ( ..$lhs:tt && $($rest:tt)+ ) => {{ Query::and($lhs, query!($($rest)*)) }};
+  ( ..$lhs:expr; && $($rest:tt)+ ) => {{ Query::and($lhs, query!($($rest)*)) }};
+  ( ..$lhs:tt || $($rest:tt)+ ) => {{ Query::or($lhs, query!($($rest)*)) }};
+  ( ..$lhs:expr; || $($rest:tt)+ ) => {{ Query::or($lhs, query!($($rest)*)) }};
   ( $key:tt in [ $($e:expr),* ] ) => {{
     let mut _temp = ::std::vec::Vec::new();
     $(_temp.push($e);)*
@@ -150,34 +383,49 @@ macro_rules! query {
   ( $key:tt >= $value:expr; ) => {{ Query::GtE { field: $key.to_owned(), value: $value.into() } }};
   ( $key:tt < $value:tt ) => {{ Query::Lt { field: $key.to_owned(), value: $value.into() } }};
   ( $key:tt < $value:expr; ) => {{ Query::Lt { field: $key.to_owned(), value: $value.into() } }};
-  ( $key:tt >= $value:tt ) => {{ Query::LtE { field: $key.to_owned(), value: $value.into() } }};
-  ( $key:tt >= $value:expr; ) => {{ Query::LtE { field: $key.to_owned(), value: $value.into() } }};
+  ( $key:tt <= $value:tt ) => {{ Query::LtE { field: $key.to_owned(), value: $value.into() } }};
+  ( $key:tt <= $value:expr; ) => {{ Query::LtE { field: $key.to_owned(), value: $value.into() } }};
   ( $key:tt %% $value:tt ) => {{ Query::Rx { field: $key.to_owned(), value: $value.into() } }};
   ( $key:tt %% $value:expr; ) => {{ Query::Rx { field: $key.to_owned(), value: $value.into() } }};
 
-  ( $key:tt $op:tt $value:tt && cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::And { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } } else { query!($key $op $value) } }};
-  ( $key:tt $op:tt $value:expr; && cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::And { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } } else { query!($key $op $value) } }};
-  ( $key:tt $op:tt $value:tt || cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::Or { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } } else { query!($key $op $value) } }};
-  ( $key:tt $op:tt $value:expr; || cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::Or { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } } else { query!($key $op $value) } }};
+  ( $key:tt $op:tt $value:tt && cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::and(query!($key $op $value), query!($($rest)*)) } else { query!($key $op $value) } }};
+  ( $key:tt $op:tt $value:expr; && cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::and(query!($key $op $value), query!($($rest)*)) } else { query!($key $op $value) } }};
+  ( $key:tt $op:tt $value:tt || cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::or(query!($key $op $value), query!($($rest)*)) } else { query!($key $op $value) } }};
+  ( $key:tt $op:tt $value:expr; || cond($condition:expr) $($rest:tt)+ ) => {{ if $condition { Query::or(query!($key $op $value), query!($($rest)*)) } else { query!($key $op $value) } }};
 
-  ( $key:tt $op:tt $value:tt && $($rest:tt)+ ) => {{ Query::And { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } }};
-  ( $key:tt $op:tt $value:expr; && $($rest:tt)+ ) => {{ Query::And { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } }};
-  ( $key:tt $op:tt $value:tt || $($rest:tt)+ ) => {{ Query::Or { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } }};
-  ( $key:tt $op:tt $value:expr; || $($rest:tt)+ ) => {{ Query::Or { left: Box::new(query!($key $op $value)), right: Box::new(query!($($rest)*)) } }};
+  ( $key:tt $op:tt $value:tt && $($rest:tt)+ ) => {{ Query::and(query!($key $op $value), query!($($rest)*)) }};
+  ( $key:tt $op:tt $value:expr; && $($rest:tt)+ ) => {{ Query::and(query!($key $op $value), query!($($rest)*)) }};
+  ( $key:tt $op:tt $value:tt || $($rest:tt)+ ) => {{ Query::or(query!($key $op $value), query!($($rest)*)) }};
+  ( $key:tt $op:tt $value:expr; || $($rest:tt)+ ) => {{ Query::or(query!($key $op $value), query!($($rest)*)) }};
 
-  ( ($($lhs:tt)+) && cond($condition:expr) $($rhs:tt)+ ) => {{ if $condition { Query::And { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } } else { query!($($lhs)*) } }};
-  ( ($($lhs:tt)+) || cond($condition:expr) $($rhs:tt)+ ) => {{ if $condition { Query::Or { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } } else { query!($($lhs)*) } }};
-  ( $($lhs:tt)+ && cond($condition:expr) ($($rhs:tt)+) ) => {{ if $condition { Query::And { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } } else { query!($($lhs)*) } }};
-  ( $($lhs:tt)+ || cond($condition:expr) ($($rhs:tt)+) ) => {{ if $condition { Query::Or { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } } else { query!($($lhs)*) } }};
+  ( ($($lhs:tt)+) && cond($condition:expr) $($rhs:tt)+ ) => {{ if $condition { Query::and(query!($($lhs)*), query!($($rhs)*)) } else { query!($($lhs)*) } }};
+  ( ($($lhs:tt)+) || cond($condition:expr) $($rhs:tt)+ ) => {{ if $condition { Query::or(query!($($lhs)*), query!($($rhs)*)) } else { query!($($lhs)*) } }};
+  ( $($lhs:tt)+ && cond($condition:expr) ($($rhs:tt)+) ) => {{ if $condition { Query::and(query!($($lhs)*), query!($($rhs)*)) } else { query!($($lhs)*) } }};
+  ( $($lhs:tt)+ || cond($condition:expr) ($($rhs:tt)+) ) => {{ if $condition { Query::or(query!($($lhs)*), query!($($rhs)*)) } else { query!($($lhs)*) } }};
 
-  ( ($($lhs:tt)+) && $($rhs:tt)+ ) => {{ Query::And { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } }};
-  ( ($($lhs:tt)+) || $($rhs:tt)+ ) => {{ Query::Or { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } }};
-  ( $($lhs:tt)+ && ($($rhs:tt)+) ) => {{ Query::And { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } }};
-  ( $($lhs:tt)+ || ($($rhs:tt)+) ) => {{ Query::Or { left: Box::new(query!($($lhs)*)), right: Box::new(query!($($rhs)*)) } }};
+  ( ($($lhs:tt)+) && $($rhs:tt)+ ) => {{ Query::and(query!($($lhs)*), query!($($rhs)*)) }};
+  ( ($($lhs:tt)+) || $($rhs:tt)+ ) => {{ Query::or(query!($($lhs)*), query!($($rhs)*)) }};
+  ( $($lhs:tt)+ && ($($rhs:tt)+) ) => {{ Query::and(query!($($lhs)*), query!($($rhs)*)) }};
+  ( $($lhs:tt)+ || ($($rhs:tt)+) ) => {{ Query::or(query!($($lhs)*), query!($($rhs)*)) }};
 
   ( ($($qq:tt)+) ) => {{ query!($($qq)*) }};
 }
 
+#[allow(unused_macros)]
+#[macro_export]
+macro_rules! query_spec {
+  ( @dir asc ) => { Direction::Asc };
+  ( @dir desc ) => { Direction::Desc };
+  ( ($($filter:tt)+) $(order by $($field:tt $dir:ident),+)? $(limit $limit:tt)? $(offset $offset:tt)? ) => {{
+    #[allow(unused_mut)]
+    let mut _spec = QuerySpec::new(query!($($filter)*));
+    $($(_spec.sort.push(Sort { field: $field.to_owned(), direction: query_spec!(@dir $dir) });)+)?
+    $(_spec.limit = Some($limit);)?
+    $(_spec.offset = Some($offset);)?
+    _spec
+  }};
+}
+
 #[cfg(test)]
 mod test {
   use crate::query::*;
@@ -186,9 +434,9 @@ mod test {
     let q = query!("deleted" == false && "b" == 5);
     let q2 = query!(..q.clone(); || "c" == 7);
     let q3 = query!(..q.clone(); && ("a" == 5 || "b" < 5));
-    let q_r = Query::And { left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }), right: Box::new(Query::Eq { field: "b".to_owned(), value: 5.into() }) };
-    let q2_r = Query::Or { left: Box::new(q_r.clone()), right: Box::new(Query::Eq { field: "c".to_owned(), value: 7.into() }) };
-    let q3_r = Query::And { left: Box::new(q_r.clone()), right: Box::new(Query::Or { left: Box::new(Query::Eq { field: "a".to_owned(), value: 5.into() }), right: Box::new(Query::Lt { field: "b".to_owned(), value: 5.into() })})};
+    let q_r = Query::and(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::Eq { field: "b".to_owned(), value: 5.into() });
+    let q2_r = Query::or(q_r.clone(), Query::Eq { field: "c".to_owned(), value: 7.into() });
+    let q3_r = Query::and(q_r.clone(), Query::or(Query::Eq { field: "a".to_owned(), value: 5.into() }, Query::Lt { field: "b".to_owned(), value: 5.into() }));
     assert_eq!(q, q_r);
     assert_eq!(q2, q2_r);
     assert_eq!(q3, q3_r);
@@ -197,15 +445,9 @@ mod test {
   #[test]
   fn query_in() {
     let q = query!("_id" in ["123", "456","789"] && "deleted" == false);
-    let q_r = Query::And {
-      left: Box::new(Query::In { field: "_id".to_owned(), value: vec!["123".to_owned(),"456".to_owned(),"789".to_owned()].into() }),
-      right: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() })
-    };
+    let q_r = Query::and(Query::In { field: "_id".to_owned(), value: vec!["123".to_owned(),"456".to_owned(),"789".to_owned()].into() }, Query::Eq { field: "deleted".to_owned(), value: false.into() });
     let q2 = query!("_id" in [123, 456,789] && "deleted" == false);
-    let q2_r = Query::And {
-      left: Box::new(Query::In { field: "_id".to_owned(), value: vec![123,456,789].into() }),
-      right: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() })
-    };
+    let q2_r = Query::and(Query::In { field: "_id".to_owned(), value: vec![123,456,789].into() }, Query::Eq { field: "deleted".to_owned(), value: false.into() });
     assert_eq!(q, q_r);
     assert_eq!(q2, q2_r);
   }
@@ -213,10 +455,7 @@ mod test {
   #[test]
   fn query_contains() {
     let q = query!("deleted" == false && "countries" contains "za");
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() }),
-    };
+    let q_r = Query::and(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() });
     assert_eq!(q, q_r);
   }
 
@@ -230,33 +469,27 @@ mod test {
   #[test]
   fn query_contains_optional_1() {
     let q = query!("deleted" == false && cond(1==1) "countries" contains "za");
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() }),
-    };
+    let q_r = Query::and(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() });
     assert_eq!(q, q_r);
   }
 
   #[test]
   fn query_contains_optional_2() {
     let q = query!("deleted" == false && cond(1==1) "countries" contains "za" || cond(1==0) "age" >= 21);
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() }),
-    };
+    let q_r = Query::and(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() });
     assert_eq!(q, q_r);
   }
 
   #[test]
   fn query_contains_optional_3() {
     let q = query!("deleted" == false && cond(1==1) "countries" contains "za" || cond(1==1) "age" >= 21);
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Or {
-        left: Box::new(Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() }),
-        right: Box::new(Query::GtE { field: "age".to_owned(), value: 21.into() })
-      }),
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Contains { field: "countries".to_owned(), value: "za".to_owned().into() },
+        Query::GtE { field: "age".to_owned(), value: 21.into() },
+      ]),
+    ]);
     assert_eq!(q, q_r);
   }
 
@@ -270,10 +503,7 @@ mod test {
   #[test]
   fn query_contains_optional_5() {
     let q = query!(("deleted" == false && cond(1==0) "countries" contains "za") || cond(1==1) "age" >= 21);
-    let q_r = Query::Or {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::GtE { field: "age".to_owned(), value: 21.into() })
-    };
+    let q_r = Query::or(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::GtE { field: "age".to_owned(), value: 21.into() });
     assert_eq!(q, q_r);
   }
 
@@ -288,22 +518,18 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::And {
-        left: Box::new(Query::And {
-          left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-          right: Box::new(Query::Or {
-            left: Box::new(Query::Eq { field: "source.id".to_owned(), value: 12.into() }),
-            right: Box::new(Query::Eq { field: "destination.id".to_owned(), value: 12.into() }),
-          })
-        }),
-        right: Box::new(Query::Eq { field: "state".to_owned(), value: "Pending".into() }),
-      }),
-      right: Box::new(Query::Or {
-        left: Box::new(Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-        right: Box::new(Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-      })
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.id".to_owned(), value: 12.into() },
+        Query::Eq { field: "destination.id".to_owned(), value: 12.into() },
+      ]),
+      Query::Eq { field: "state".to_owned(), value: "Pending".into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() },
+        Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() },
+      ]),
+    ]);
     assert_eq!(q, q_r);
   }
 
@@ -318,16 +544,14 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::And {
-        left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-        right: Box::new(Query::Eq { field: "state".to_owned(), value: "Pending".into() }),
-      }),
-      right: Box::new(Query::Or {
-        left: Box::new(Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-        right: Box::new(Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-      })
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Eq { field: "state".to_owned(), value: "Pending".into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() },
+        Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() },
+      ]),
+    ]);
     assert_eq!(q, q_r);
   }
 
@@ -342,19 +566,17 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::And {
-        left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-        right: Box::new(Query::Or {
-          left: Box::new(Query::Eq { field: "source.id".to_owned(), value: 12.into() }),
-          right: Box::new(Query::Eq { field: "destination.id".to_owned(), value: 12.into() }),
-        })
-      }),
-      right: Box::new(Query::Or {
-        left: Box::new(Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-        right: Box::new(Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-      })
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.id".to_owned(), value: 12.into() },
+        Query::Eq { field: "destination.id".to_owned(), value: 12.into() },
+      ]),
+      Query::Or(vec![
+        Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() },
+        Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() },
+      ]),
+    ]);
     assert_eq!(q, q_r);
   }
 
@@ -369,16 +591,14 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::And {
-        left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-        right: Box::new(Query::Or {
-          left: Box::new(Query::Eq { field: "source.id".to_owned(), value: 12.into() }),
-          right: Box::new(Query::Eq { field: "destination.id".to_owned(), value: 12.into() }),
-        })
-      }),
-      right: Box::new(Query::Eq { field: "state".to_owned(), value: "Pending".into() }),
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.id".to_owned(), value: 12.into() },
+        Query::Eq { field: "destination.id".to_owned(), value: 12.into() },
+      ]),
+      Query::Eq { field: "state".to_owned(), value: "Pending".into() },
+    ]);
     assert_eq!(q, q_r);
   }
 
@@ -393,10 +613,7 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Eq { field: "state".to_owned(), value: "Pending".into() }),
-    };
+    let q_r = Query::and(Query::Eq { field: "deleted".to_owned(), value: false.into() }, Query::Eq { field: "state".to_owned(), value: "Pending".into() });
     assert_eq!(q, q_r);
   }
 
@@ -426,13 +643,13 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Or {
-        left: Box::new(Query::Eq { field: "source.id".to_owned(), value: 12.into() }),
-        right: Box::new(Query::Eq { field: "destination.id".to_owned(), value: 12.into() }),
-      })
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.id".to_owned(), value: 12.into() },
+        Query::Eq { field: "destination.id".to_owned(), value: 12.into() },
+      ]),
+    ]);
     assert_eq!(q, q_r);
   }
 
@@ -447,13 +664,40 @@ mod test {
         cond(state.is_some()) "state" == { state.unwrap() }
       ) && cond(currency_iso.is_some()) ("source.currency_iso" == { currency_iso.clone().unwrap() } || ("destination.currency_iso" == { currency_iso.clone().unwrap() }))
     );
-    let q_r = Query::And {
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::Or {
-        left: Box::new(Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-        right: Box::new(Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() }),
-      })
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Eq { field: "source.currency_iso".to_owned(), value: "USD".to_owned().into() },
+        Query::Eq { field: "destination.currency_iso".to_owned(), value: "USD".to_owned().into() },
+      ]),
+    ]);
+    assert_eq!(q, q_r);
+  }
+
+  #[test]
+  fn query_spec_sort_and_paging() {
+    let limit = 20;
+    let q = query_spec!(("deleted" == false) order by "created_at" desc, "id" asc limit limit offset 40);
+    let q_r = QuerySpec::new(Query::Eq { field: "deleted".to_owned(), value: false.into() })
+      .sort(Sort::desc("created_at"))
+      .sort(Sort::asc("id"))
+      .limit(20)
+      .offset(40);
     assert_eq!(q, q_r);
+    let q2 = query_spec!(("deleted" == false && "b" == 5) limit 5);
+    let q2_r = QuerySpec::new(query!("deleted" == false && "b" == 5)).limit(5);
+    assert_eq!(q2, q2_r);
+  }
+
+  #[cfg(any(feature = "cypher", feature = "tantivy"))]
+  #[test]
+  fn regex_anchored() {
+    assert_eq!(anchored("on"), ".*(?:on).*");
+    assert_eq!(anchored("^jo"), "(?:jo).*");
+    assert_eq!(anchored("^a|b$"), "(?:(?:a).*|.*(?:b))");
+    assert_eq!(anchored("a|b"), "(?:.*(?:a).*|.*(?:b).*)");
+    assert_eq!(anchored("^(a|b)$"), "(?:(a|b))");
+    assert_eq!(anchored("^[|]\\$"), "(?:[|]\\$).*");
+    assert_eq!(anchored("^a\\\\$"), "(?:a\\\\)");
   }
 }
\ No newline at end of file
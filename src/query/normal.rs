@@ -0,0 +1,169 @@
+use std::fmt;
+
+use crate::query::*;
+use crate::query::simplify::falsity;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  TooManyClauses { max: usize },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::TooManyClauses { max } => write!(f, "normal form needs more than {} clauses", max),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+// Equality flips to inequality, which treats missing and null fields the
+// same way in every backend. Ordering comparisons keep their NOT: in SQL
+// NOT (a > 1) is a <= 1, but in Mongo it also matches documents without `a`.
+fn negate(query: Query) -> Query {
+  match query {
+    Query::And(queries) => Query::any(queries.into_iter().map(negate)),
+    Query::Or(queries) => Query::all(queries.into_iter().map(negate)),
+    Query::Not(query) => query.to_nnf(),
+    Query::Eq { field, value } => Query::Neq { field, value },
+    Query::Neq { field, value } => Query::Eq { field, value },
+    query => Query::Not(Box::new(query)),
+  }
+}
+
+fn push(clause: &mut Vec<Query>, literal: &Query) {
+  if !clause.contains(literal) {
+    clause.push(literal.clone());
+  }
+}
+
+// Expands a query in negation normal form into clauses of literals. For CNF
+// the outer list is joined by AND and each clause by OR, for DNF the other
+// way around. An empty outer list is the identity of the outer operator and
+// an empty clause is the identity of the inner one.
+pub(crate) fn clauses(query: Query, cnf: bool, max: usize) -> Result<Vec<Vec<Query>>, Error> {
+  let clauses = match (query, cnf) {
+    (Query::And(queries), true) | (Query::Or(queries), false) => {
+      let mut out = vec![];
+      for query in queries {
+        for clause in clauses(query, cnf, max)? {
+          if !out.contains(&clause) {
+            out.push(clause);
+          }
+        }
+      }
+      out
+    },
+    (Query::And(queries), false) | (Query::Or(queries), true) => {
+      let mut out = vec![vec![]];
+      for query in queries {
+        let other = clauses(query, cnf, max)?;
+        if out.len().saturating_mul(other.len()) > max {
+          return Err(Error::TooManyClauses { max });
+        }
+        let mut product = vec![];
+        for left in &out {
+          for right in &other {
+            let mut clause = left.clone();
+            right.iter().for_each(|literal| push(&mut clause, literal));
+            if !product.contains(&clause) {
+              product.push(clause);
+            }
+          }
+        }
+        out = product;
+      }
+      out
+    },
+    (Query::None, true) => vec![],
+    (Query::None, false) => vec![vec![]],
+    (query, true) if query.is_false() => vec![vec![]],
+    (query, false) if query.is_false() => vec![],
+    (query, _) => vec![vec![query]],
+  };
+  match clauses.len() > max {
+    true => Err(Error::TooManyClauses { max }),
+    false => Ok(clauses),
+  }
+}
+
+fn build(clauses: Vec<Vec<Query>>, cnf: bool) -> Query {
+  match (clauses.is_empty(), clauses.iter().any(|clause| clause.is_empty()), cnf) {
+    (true, _, true) | (false, true, false) => Query::None,
+    (true, _, false) | (false, true, true) => falsity(),
+    (false, false, true) => Query::all(clauses.into_iter().map(Query::any)),
+    (false, false, false) => Query::any(clauses.into_iter().map(Query::all)),
+  }
+}
+
+impl Query {
+  pub fn to_nnf(self) -> Query {
+    match self {
+      Query::And(queries) => Query::all(queries.into_iter().map(Query::to_nnf)),
+      Query::Or(queries) => Query::any(queries.into_iter().map(Query::to_nnf)),
+      Query::Not(query) => negate(*query),
+      query => query,
+    }
+  }
+  // The result is an And of Or clauses, so every clause holding a single
+  // predicate is one that any matching document has to satisfy.
+  pub fn to_cnf(self, max_clauses: usize) -> Result<Query, Error> {
+    Ok(build(clauses(self.to_nnf(), true, max_clauses)?, true))
+  }
+  // The result is an Or of And branches that can each be run on their own.
+  pub fn to_dnf(self, max_clauses: usize) -> Result<Query, Error> {
+    Ok(build(clauses(self.to_nnf(), false, max_clauses)?, false))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::normal::*;
+  use crate::query;
+
+  #[test]
+  fn negation_normal_form() {
+    let q = !query!("a" == 1 && ("b" > 2 || "c" < 3));
+    assert_eq!(q.to_nnf(), Query::or(query!("a" != 1), Query::and(!query!("b" > 2), !query!("c" < 3))));
+    let q = !Query::or(!query!("a" >= 1), query!("tags" contains "x"));
+    assert_eq!(q.to_nnf(), Query::and(query!("a" >= 1), !query!("tags" contains "x")));
+    // Documents without `a` match the negation but not `a > 1` in Mongo.
+    let q = !query!("a" <= 1);
+    assert_eq!(q.clone().to_nnf(), q);
+  }
+
+  #[test]
+  fn conjunctive_normal_form() {
+    let q = query!("tenant" == 7 && ("a" == 1 || ("b" == 2 && "c" == 3)));
+    let q_r = Query::And(vec![
+      query!("tenant" == 7),
+      Query::Or(vec![query!("a" == 1), query!("b" == 2)]),
+      Query::Or(vec![query!("a" == 1), query!("c" == 3)]),
+    ]);
+    assert_eq!(q.to_cnf(16), Ok(q_r));
+    assert_eq!(query!("a" == 1 || "a" == 1).to_cnf(16), Ok(query!("a" == 1)));
+    assert_eq!(Query::None.to_cnf(16), Ok(Query::None));
+    assert_eq!(Query::or(query!("a" == 1), !Query::None).to_cnf(16), Ok(query!("a" == 1)));
+  }
+
+  #[test]
+  fn disjunctive_normal_form() {
+    let q = !Query::or(query!("a" != 1), Query::and(query!("b" <= 2), query!("c" >= 3)));
+    let q_r = Query::Or(vec![
+      Query::and(query!("a" == 1), !query!("b" <= 2)),
+      Query::and(query!("a" == 1), !query!("c" >= 3)),
+    ]);
+    assert_eq!(q.to_dnf(16), Ok(q_r));
+    assert_eq!(Query::and(query!("a" == 1), !Query::None).to_dnf(16), Ok(!Query::None));
+    assert_eq!(Query::or(query!("a" == 1), Query::None).to_dnf(16), Ok(Query::None));
+  }
+
+  #[test]
+  fn clause_limit() {
+    let q = Query::all((0..12).map(|i| Query::or(Query::Eq { field: format!("a{}", i), value: 1.into() }, Query::Eq { field: format!("b{}", i), value: 1.into() })));
+    assert_eq!(q.clone().to_cnf(12).map(|q| q.fields().len()), Ok(24));
+    assert_eq!(q.clone().to_dnf(1024), Err(Error::TooManyClauses { max: 1024 }));
+    assert_eq!(q.to_dnf(4096).map(|q| if let Query::Or(branches) = q { branches.len() } else { 0 }), Ok(4096));
+  }
+}
@@ -1,563 +1,201 @@
-use std::collections::LinkedList;
-use std::fmt::{self, Write};
-use lexer::*;
+use std::fmt;
+use querylib_grammar::{Expr, Term};
 
 use crate::query::*;
 
-#[derive(Debug, Clone,  PartialEq)]
-pub enum Operator {
-  And,
-  Or,
-}
+pub mod sql;
+
+pub use querylib_grammar::{Operand, Operator, Token, TokenValue};
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Operand {
-  Eq,
-  Neq,
-  Gt,
-  GtE,
-  Lt,
-  LtE,
-  Rx,
-  In,
-  Contains,
+pub enum Error {
+  Invalid { position: usize, message: &'static str },
+  UnexpectedToken { position: usize, found: String },
+  UnexpectedEnd,
+  InvalidField { position: usize, field: String },
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum TokenValue {
-  Number(i64),
-  Float(f64),
-  String(String),
-  Bool(bool),
-
-  Array(LinkedList<TokenValue>),
-
-  Operand(Operand),
-  Operator(Operator),
-  Identifier(String),
-  Grouped(LinkedList<Token>),
+impl Error {
+  fn unexpected(token: &Token) -> Error {
+    Error::UnexpectedToken { position: token.start, found: token.value.to_string() }
+  }
 }
 
-impl From<TokenValue> for Value {
-  fn from(v: TokenValue) -> Value {
-    match v {
-      TokenValue::Number(n) => Value::Number(n),
-      TokenValue::Float(f) => Value::Float(f),
-      TokenValue::String(s) => Value::String(s),
-      TokenValue::Bool(b) => Value::Bool(b),
-      TokenValue::Array(l) => Value::Array(Box::new(l.into_iter().map(|v| {
-        v.into()
-      }).collect())),
-      _ => Value::None,
+impl From<querylib_grammar::Error> for Error {
+  fn from(error: querylib_grammar::Error) -> Error {
+    match error {
+      querylib_grammar::Error::Invalid { start, message, .. } => Error::Invalid { position: start, message },
+      querylib_grammar::Error::Unexpected { token, .. } => Error::unexpected(&token),
+      querylib_grammar::Error::UnexpectedEnd { .. } => Error::UnexpectedEnd,
     }
   }
 }
 
-impl fmt::Display for TokenValue {
+impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      &TokenValue::Number(ref n) => write!(f, "{}", n),
-      &TokenValue::String(ref s) => write!(f, "{:?}", s),
-      &TokenValue::Bool(ref b) => write!(f, "{}", b),
-      &TokenValue::Float(ref fv) => write!(f, "{:?}", fv),
-      &TokenValue::Operand(ref s) => write!(f, ":{:?}", s),
-      &TokenValue::Operator(ref s) => write!(f, ":{:?}", s),
-      &TokenValue::Identifier(ref s) => write!(f, "{}", s),
-      &TokenValue::Array(ref array) => {
-        f.write_char('[')?;
-        let mut index = 0;
-        for token in array {
-          write!(f, "{}", token)?;
-          index += 1;
-          if index < array.len() {
-            f.write_str(", ")?;
-          }
-        }
-        f.write_char(']')
-      }
-      &TokenValue::Grouped(ref grouped) => {
-        f.write_char('(')?;
-        let mut index = 0;
-        for token in grouped {
-          write!(f, "{}", token.value())?;
-          index += 1;
-          if index < grouped.len() {
-            f.write_str(", ")?;
-          }
-        }
-        f.write_char(')')
-      }
+      Error::Invalid { position, message } => write!(f, "{} at {}", message, position),
+      Error::UnexpectedToken { position, found } => write!(f, "unexpected {} at {}", found, position),
+      Error::UnexpectedEnd => write!(f, "unexpected end of input"),
+      Error::InvalidField { position, field } => write!(f, "invalid field name {:?} at {}", field, position),
     }
   }
 }
 
-pub type Token = lexer::Token<TokenValue>;
-pub type TokenError = lexer::TokenError<&'static str>;
-
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct WhitespaceReader;
-
-impl Reader<Token, TokenError> for WhitespaceReader {
-  fn read(&self, _reader: &Readers<Token, TokenError>, input: &mut dyn Input, _current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        if ch.is_whitespace() || ch == ',' {
-          while let Some(ch) = input.peek(next, 0) {
-            if ch.is_whitespace() || ch == ',' {
-              input.read(next);
-            } else {
-              break;
-            }
-          }
-          ReaderResult::Empty
-        } else {
-          ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
+impl std::error::Error for Error {}
+
+// `{expr}` and `cond(expr)` only make sense inside q!, so they are rejected
+// here along with anything else that is not a literal.
+fn value(token: &Token) -> Result<Value, Error> {
+  match &token.value {
+    TokenValue::Number(n) => Ok(Value::Number(*n)),
+    TokenValue::Float(f) => Ok(Value::Float(*f)),
+    TokenValue::String(s) => Ok(Value::String(s.clone())),
+    TokenValue::Bool(b) => Ok(Value::Bool(*b)),
+    TokenValue::Array(tokens) => Ok(Value::Array(Box::new(tokens.iter().map(value).collect::<Result<_, _>>()?))),
+    TokenValue::Identifier(_) => Ok(Value::None),
+    _ => Err(Error::unexpected(token)),
   }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct NumberReader;
-
-impl Reader<Token, TokenError> for NumberReader {
-  fn read(&self, _reader: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        if ch.is_numeric() {
-          let mut string = String::new();
-          string.push(ch);
-          while let Some(ch) = input.peek(next, 0) {
-            if ch.is_numeric() || ch == '.' {
-              input.read(next);
-              string.push(ch);
-            } else {
-              break;
-            }
-          }
-          if string.contains(".") {
-            ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Float(string.parse().unwrap()),
-            ))  
-          } else {
-            ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Number(string.parse().unwrap()),
-            ))
-          }
-        } else {
-          ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
+impl From<Operand> for schema::Operator {
+  fn from(operand: Operand) -> schema::Operator {
+    match operand {
+      Operand::Eq => schema::Operator::Eq,
+      Operand::Neq => schema::Operator::Neq,
+      Operand::Gt => schema::Operator::Gt,
+      Operand::GtE => schema::Operator::GtE,
+      Operand::Lt => schema::Operator::Lt,
+      Operand::LtE => schema::Operator::LtE,
+      Operand::Rx => schema::Operator::Rx,
+      Operand::In => schema::Operator::In,
+      Operand::Contains => schema::Operator::Contains,
     }
   }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct StringReader;
-
-impl Reader<Token, TokenError> for StringReader {
-  fn read(&self, _reader: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        if ch == '\'' {
-          let mut string = String::new();
-          while let Some(ch) = input.read(next) {
-            if ch == '\'' {
-              break;
-            } else {
-              string.push(ch);
-            }
-          }
-          ReaderResult::Some(Token::new(
-            TokenMeta::new_state_meta(current, next),
-            TokenValue::String(string),
-          ))
-        } else {
-          ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
+fn term(term: &Term) -> Result<Query, Error> {
+  match term {
+    Term::Group(group) => expr(group),
+    Term::Compare { field, operand, value: token } => Ok(schema::Operator::from(*operand).query(field.clone(), value(token)?)),
   }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct OperandReader;
-
-impl Reader<Token, TokenError> for OperandReader {
-  fn read(&self, _reader: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        match ch {
-          'i' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == 'n' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::In))
-                  )
-                } else {
-                  ReaderResult::None
-                }
-              },
-              None => ReaderResult::None,
-            }
-          },
-          '=' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '=' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::Eq))
-                  )
-                } else {
-                  ReaderResult::None
-                }
-              },
-              None => ReaderResult::None,
-            }
-          },
-          '!' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '=' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::Neq))
-                  )
-                } else {
-                  ReaderResult::None
-                }
-              },
-              None => ReaderResult::None,
-            }
-          },
-          '#' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '=' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::Rx))
-                  )
-                } else {
-                  ReaderResult::None
-                }
-              },
-              None => ReaderResult::None,
-            }
-          },
-          '<' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '=' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::LtE))
-                  )
-                } else {
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::Lt))
-                  )
-                }
-              },
-              None => ReaderResult::Some(Token::new(
-                TokenMeta::new_state_meta(current, next),
-                TokenValue::Operand(Operand::Lt))
-              ),
-            }
-          },
-          '>' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '=' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::GtE))
-                  )
-                } else {
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operand(Operand::Gt))
-                  )
-                }
-              },
-              None => ReaderResult::Some(Token::new(
-                TokenMeta::new_state_meta(current, next),
-                TokenValue::Operand(Operand::Gt))
-              ),
-            }
-          },
-          _ => ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
+// The grammar associates to the right, so the links are folded from the
+// back; only nested groups recurse.
+fn expr(expr: &Expr) -> Result<Query, Error> {
+  if let Some(cond) = expr.rest.iter().find_map(|link| link.cond.as_ref()) {
+    return Err(Error::unexpected(cond));
+  }
+  let mut query = term(expr.rest.last().map(|link| &link.term).unwrap_or(&expr.first))?;
+  for i in (0..expr.rest.len()).rev() {
+    let left = term(if i == 0 { &expr.first } else { &expr.rest[i - 1].term })?;
+    query = match expr.rest[i].operator {
+      Operator::And => Query::and(left, query),
+      Operator::Or => Query::or(left, query),
+    };
   }
+  Ok(query)
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct OperatorReader;
-
-impl Reader<Token, TokenError> for OperatorReader {
-  fn read(&self, _reader: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        match ch {
-          '&' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '&' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operator(Operator::And))
-                  )
-                } else {
-                  ReaderResult::None
-                }
-              },
-              None => ReaderResult::None,
-            }
-          },
-          '|' => {
-            match input.peek(next, 0) {
-              Some(ch) => {
-                if ch == '|' {
-                  input.read(next);
-                  ReaderResult::Some(Token::new(
-                    TokenMeta::new_state_meta(current, next),
-                    TokenValue::Operator(Operator::Or))
-                  )
-                } else {
-                  ReaderResult::None
-                }
-              },
-              None => ReaderResult::None
-            }
-          },
-          _ => ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
+fn parse(tokens: &[Token]) -> Result<Query, Error> {
+  match querylib_grammar::parse(tokens)? {
+    Some(filter) => expr(&filter),
+    None => Ok(Query::None),
   }
 }
 
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+  Ok(querylib_grammar::tokenize(s)?)
+}
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct IdentifierReader;
-
-impl Reader<Token, TokenError> for IdentifierReader {
-  fn read(&self, _reader: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        if ch.is_alphabetic() || ch == '_' {
-          let mut string = String::new();
-          string.push(ch);
-          while let Some(ch) = input.peek(next, 0) {
-            if ch.is_alphanumeric() || ch == '.' || ch == '_' {
-              input.read(next);
-              string.push(ch);
-            } else {
-              break;
-            }
-          }
-          match string.as_str() {
-            "contains" => ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Operand(Operand::Contains)
-            )),
-            "in" => ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Operand(Operand::In)
-            )),
-            "true" => ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Bool(true),
-            )),
-            "false" => ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Bool(false),
-            )),
-            _ => ReaderResult::Some(Token::new(
-              TokenMeta::new_state_meta(current, next),
-              TokenValue::Identifier(string),
-            ))
-          }
-        } else {
-          ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
+fn is_keyword(token: Option<&Token>, keyword: &str) -> bool {
+  match token.map(|t| &t.value) {
+    Some(TokenValue::Identifier(ident)) => ident.eq_ignore_ascii_case(keyword),
+    _ => false,
   }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct GroupedReader;
-
-impl Reader<Token, TokenError> for GroupedReader {
-  fn read(&self, readers: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        if ch == '(' {
-          let mut group = LinkedList::new();
-
-          while let Some(ch) = input.peek(next, 0) {
-            if ch == ')' {
-              input.read(next);
-              break;
-            } else {
-              match lexer::read(readers, input, next) {
-                Some(Ok(token)) => {
-                  group.push_back(token);
-                },
-                Some(Err(error)) => {
-                  return ReaderResult::Err(error);
-                },
-                _ => {
-                  break;
-                }
-              }
-            }
-          }
-
-          ReaderResult::Some(Token::new(
-            TokenMeta::new_state_meta(current, next),
-            TokenValue::Grouped(group),
-          ))
-        } else {
-          ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
-  }
+fn is_number(token: Option<&Token>) -> bool {
+  matches!(token.map(|t| &t.value), Some(TokenValue::Number(_)))
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub struct ArrayReader;
-
-impl Reader<Token, TokenError> for ArrayReader {
-  fn read(&self, _readers: &Readers<Token, TokenError>, input: &mut dyn Input, current: &State, next: &mut State) -> ReaderResult<Token, TokenError> {
-    match input.read(next) {
-      Some(ch) => {
-        if ch == '[' {
-          let mut array = LinkedList::new();
-          while let Some(ch) = input.read(next) {
-            if ch == '\'' {
-              let mut string = String::new();
-              while let Some(ch) = input.read(next) {
-                if ch == '\'' {
-                  break;
-                } else {
-                  string.push(ch);
-                }
-              }
-              array.push_back(TokenValue::String(string));
-            } else if ch.is_numeric() {
-              let mut string = String::new();
-              string.push(ch);
-              while let Some(ch) = input.peek(next, 0) {
-                if ch.is_numeric() || ch == '.' {
-                  input.read(next);
-                  string.push(ch);
-                } else {
-                  break;
-                }
-              }
-              if string.contains(".") {
-                array.push_back(TokenValue::Float(string.parse().unwrap()))
-              } else {
-                array.push_back(TokenValue::Number(string.parse().unwrap()))
-              }
-            } else if ch == ']' {
-              input.read(next);
-              break;
-            }
-          }
-          ReaderResult::Some(Token::new(
-            TokenMeta::new_state_meta(current, next),
-            TokenValue::Array(array),
-          ))
-        } else {
-          ReaderResult::None
-        }
-      },
-      None => ReaderResult::None,
-    }
-  }
+fn is_clause_start(tokens: &[Token], i: usize) -> bool {
+  (is_keyword(tokens.get(i), "order") && is_keyword(tokens.get(i + 1), "by")) ||
+  ((is_keyword(tokens.get(i), "limit") || is_keyword(tokens.get(i), "offset")) && is_number(tokens.get(i + 1)))
 }
 
+// Sort fields end up in ORDER BY and $sort keys, so only plain dotted
+// identifiers are accepted.
+fn is_path(field: &str) -> bool {
+  field.split('.').all(|segment| {
+    segment.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+  })
+}
 
-fn parse(tokens: LinkedList<Token>, state: Query) -> Query {
-  let mut list = tokens.clone();
-  match list.pop_front() {
-    None => state,
-    Some(head) => {
-      match head.value() {
-        TokenValue::Grouped(group) => {
-          let q = parse(group.clone(), Query::None);
-          parse(list, q)
+fn parse_spec(tokens: Vec<Token>) -> Result<QuerySpec, Error> {
+  let split = (0..tokens.len()).find(|i| is_clause_start(&tokens, *i)).unwrap_or(tokens.len());
+  let filter = parse(&tokens[..split])?;
+  let mut spec = QuerySpec::new(filter);
+  let mut i = split;
+  while i < tokens.len() {
+    if is_keyword(tokens.get(i), "order") {
+      i += 2;
+      while let Some(TokenValue::Identifier(field)) = tokens.get(i).map(|t| &t.value) {
+        if is_clause_start(&tokens, i) {
+          break;
+        }
+        if !is_path(field) {
+          return Err(Error::InvalidField { position: tokens[i].start, field: field.clone() });
         }
-        TokenValue::Operator(Operator::And) => {
-          Query::And {left: Box::new(state), right: Box::new(parse(list.clone(), Query::None))}
-        },
-        TokenValue::Operator(Operator::Or) => {
-          Query::Or {left: Box::new(state), right: Box::new(parse(list, Query::None))}
-        },
-        TokenValue::Identifier(ident) => {
-          let op = list.pop_front().unwrap();
-          let val = list.pop_front().unwrap();
-          let query = match op.value() {
-            TokenValue::Operand(Operand::Eq) => Query::Eq { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::Neq) => Query::Neq { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::Gt) => Query::Gt { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::GtE) => Query::GtE { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::Lt) => Query::Lt { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::LtE) => Query::LtE { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::Rx) => Query::Rx { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::In) => Query::In { field: ident.clone(), value: val.value().clone().into() },
-            TokenValue::Operand(Operand::Contains) => Query::Contains { field: ident.clone(), value: val.value().clone().into() },
-            _ => Query::None,
-          };
-          parse(list, query)
-        },
-        _ => Query::None,
-      } 
+        i += 1;
+        let direction = if is_keyword(tokens.get(i), "desc") {
+          i += 1;
+          Direction::Desc
+        } else {
+          if is_keyword(tokens.get(i), "asc") {
+            i += 1;
+          }
+          Direction::Asc
+        };
+        spec.sort.push(Sort { field: field.clone(), direction });
+      }
+    } else if let (true, Some(TokenValue::Number(n))) = (is_keyword(tokens.get(i), "limit"), tokens.get(i + 1).map(|t| &t.value)) {
+      spec.limit = Some(*n as u64);
+      i += 2;
+    } else if let (true, Some(TokenValue::Number(n))) = (is_keyword(tokens.get(i), "offset"), tokens.get(i + 1).map(|t| &t.value)) {
+      spec.offset = Some(*n as u64);
+      i += 2;
+    } else {
+      return Err(Error::unexpected(&tokens[i]));
     }
   }
+  Ok(spec)
 }
 
+pub fn try_from_str(s: &str) -> Result<Query, Error> {
+  parse(&tokenize(s)?)
+}
+
+// Panics on malformed input; use try_from_str for filters that do not come
+// from the program itself.
 pub fn from_str(s: &str) -> Query {
-  let readers = ReadersBuilder::new()
-    .add(WhitespaceReader)
-    .add(NumberReader)
-    .add(StringReader)
-    .add(OperandReader)
-    .add(OperatorReader)
-    .add(IdentifierReader)
-    .add(GroupedReader)
-    .add(ArrayReader)
-    .build();
-  let _lexer = readers.read(s.chars());
-  let tokens: LinkedList<Token> = _lexer.map(Result::unwrap).collect();
-  parse(tokens, Query::None)
+  try_from_str(s).unwrap_or_else(|error| panic!("invalid filter {:?}: {}", s, error))
+}
+
+pub fn spec_from_str(s: &str) -> Result<QuerySpec, Error> {
+  parse_spec(tokenize(s)?)
+}
+
+pub fn projection_from_str(s: &str) -> Projection {
+  let s = s.trim();
+  let fields = s.strip_prefix("fields=").unwrap_or(s);
+  fields.split(',').map(str::trim).filter(|f| !f.is_empty()).fold(Projection::new(), |p, f| {
+    match f.strip_prefix('-') {
+      Some(excluded) => p.exclude(excluded),
+      None => p.include(f.strip_prefix('+').unwrap_or(f)),
+    }
+  })
 }
 
 #[cfg(test)]
@@ -567,19 +205,111 @@ mod test {
   fn lexer_works() {
     let squery = "deleted == false && _b.bah.h1 == 5 && (a == 5 || b < 5) || c in ['1','2','3','4']";
     let query = parse::from_str(squery);
-    let q_r = Query::And { 
-      left: Box::new(Query::Eq { field: "deleted".to_owned(), value: false.into() }),
-      right: Box::new(Query::And {
-        left: Box::new(Query::Eq { field: "_b.bah.h1".to_owned(), value: 5.into() }),
-        right: Box::new(Query::Or {
-          left: Box::new(Query::Or {
-            left: Box::new(Query::Eq { field: "a".to_owned(), value: 5.into() }),
-            right: Box::new(Query::Lt { field: "b".to_owned(), value: 5.into() })
-          }),
-          right: Box::new(Query::In { field: "c".to_owned(), value: vec!["1".to_string(), "2".to_string(), "3".to_string(), "4".to_string()].into()})
-        })
-      })
-    };
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Eq { field: "_b.bah.h1".to_owned(), value: 5.into() },
+      Query::Or(vec![
+        Query::Eq { field: "a".to_owned(), value: 5.into() },
+        Query::Lt { field: "b".to_owned(), value: 5.into() },
+        Query::In { field: "c".to_owned(), value: vec!["1".to_string(), "2".to_string(), "3".to_string(), "4".to_string()].into() },
+      ]),
+    ]);
     assert_eq!(query, q_r);
   }
+
+  #[test]
+  fn spec_order_by_limit() {
+    let squery = "deleted == false && (a == 5 || b < 5) order by created_at desc, id limit 20 offset 40";
+    let spec = parse::spec_from_str(squery).unwrap();
+    let q_r = QuerySpec::new(Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::Eq { field: "a".to_owned(), value: 5.into() },
+        Query::Lt { field: "b".to_owned(), value: 5.into() },
+      ]),
+    ]));
+    let q_r = q_r.sort(Sort::desc("created_at")).sort(Sort::asc("id")).limit(20).offset(40);
+    assert_eq!(spec, q_r);
+  }
+
+  #[test]
+  fn spec_without_clauses() {
+    let spec = parse::spec_from_str("limit == 5 && order == 'x'").unwrap();
+    assert_eq!(spec, QuerySpec::new(parse::from_str("limit == 5 && order == 'x'")));
+    let spec = parse::spec_from_str("a == 1 limit 10").unwrap();
+    assert_eq!(spec, QuerySpec::new(Query::Eq { field: "a".to_owned(), value: 1.into() }).limit(10));
+  }
+
+  #[test]
+  fn spec_errors() {
+    let invalid = |position| Err(parse::Error::Invalid { position, message: "invalid number" });
+    assert_eq!(parse::spec_from_str("a == 1 limit 99999999999999999999"), invalid(13));
+    assert_eq!(parse::spec_from_str("a == 1.2.3"), invalid(5));
+    assert_eq!(parse::try_from_str("a in [1, 2.2.2]"), Err(parse::Error::Invalid { position: 9, message: "invalid number" }));
+    assert_eq!(parse::spec_from_str("a == 1 limit 10 extra"), Err(parse::Error::UnexpectedToken { position: 16, found: "extra".to_owned() }));
+    assert_eq!(parse::spec_from_str("a == 1 order by a..b"), Err(parse::Error::InvalidField { position: 16, field: "a..b".to_owned() }));
+    assert_eq!(parse::spec_from_str("a =="), Err(parse::Error::UnexpectedEnd));
+  }
+
+  #[test]
+  fn long_filter_is_flat() {
+    let squery = (0..5000).map(|i| format!("id == {}", i)).collect::<Vec<String>>().join(" || ");
+    match parse::from_str(&squery) {
+      Query::Or(queries) => {
+        assert_eq!(queries.len(), 5000);
+        assert_eq!(queries[4999], Query::Eq { field: "id".to_owned(), value: 4999.into() });
+      },
+      query => panic!("expected a flat Or, got {:?}", query),
+    }
+  }
+
+  #[cfg(feature = "derive")]
+  #[test]
+  fn q_matches_from_str() {
+    use crate::q;
+    assert_eq!(q!("deleted == false && _b.bah.h1 == 5 && (a == 5 || b < 5) || c in ['1','2','3','4']"),
+      parse::from_str("deleted == false && _b.bah.h1 == 5 && (a == 5 || b < 5) || c in ['1','2','3','4']"));
+    assert_eq!(q!("a >= 1.5 && b <= 2 && c != 'x' && d #= '^jo' && tags contains 'za'"),
+      parse::from_str("a >= 1.5 && b <= 2 && c != 'x' && d #= '^jo' && tags contains 'za'"));
+    assert_eq!(q!("(a > 1 || a < 0) && ((b == true))"), parse::from_str("(a > 1 || a < 0) && ((b == true))"));
+    assert_eq!(q!(""), Query::None);
+  }
+
+  #[cfg(feature = "derive")]
+  #[test]
+  fn q_interpolation_and_cond() {
+    use crate::{q, query};
+    let min_age = 21;
+    let ids = vec!["a".to_owned(), "b".to_owned()];
+    let q = q!("deleted == false && age >= {min_age} && _id in {ids.clone()} && name == {format!(\"{}!\", \"jo\")}");
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::GtE { field: "age".to_owned(), value: 21.into() },
+      Query::In { field: "_id".to_owned(), value: ids.into() },
+      Query::Eq { field: "name".to_owned(), value: "jo!".into() },
+    ]);
+    assert_eq!(q, q_r);
+    let state: Option<&str> = None;
+    let q = q!("deleted == false && cond(state.is_some()) state == {state.unwrap()}");
+    assert_eq!(q, query!("deleted" == false));
+    let state = Some("Pending");
+    let q = q!("deleted == false && cond(state.is_some()) state == {state.unwrap()} || cond(1 == 0) age > 1");
+    assert_eq!(q, query!("deleted" == false && "state" == "Pending"));
+    let q = q!("(deleted == false && cond(1 == 0) a == 1) || cond(1 == 1) age >= 21");
+    assert_eq!(q, query!("deleted" == false || "age" >= 21));
+  }
+
+  #[cfg(feature = "derive")]
+  #[test]
+  fn q_rejects_bad_filters() {
+    trybuild::TestCases::new().compile_fail("tests/ui/q_*.rs");
+  }
+
+  #[test]
+  fn projection_fields() {
+    let p = parse::projection_from_str("fields=a, b.c,-_id");
+    assert_eq!(p, Projection::new().include("a").include("b.c").exclude("_id"));
+    assert_eq!(parse::projection_from_str("a"), Projection::from(vec!["a"]));
+    assert!(parse::projection_from_str("fields=").is_empty());
+  }
 }
\ No newline at end of file
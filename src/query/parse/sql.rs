@@ -0,0 +1,425 @@
+use std::fmt;
+
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  UnexpectedChar { position: usize, found: char },
+  UnterminatedString { position: usize },
+  UnexpectedToken { position: usize, found: String },
+  UnexpectedEnd,
+  MissingParam { position: usize, index: usize },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::UnexpectedChar { position, found } => write!(f, "unexpected character {:?} at {}", found, position),
+      Error::UnterminatedString { position } => write!(f, "unterminated string starting at {}", position),
+      Error::UnexpectedToken { position, found } => write!(f, "unexpected {} at {}", found, position),
+      Error::UnexpectedEnd => write!(f, "unexpected end of input"),
+      Error::MissingParam { position, index } => write!(f, "no value bound for ${} at {}", index, position),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Ident(String),
+  Str(String),
+  Number(i64),
+  Float(f64),
+  Param(usize),
+  Op(&'static str),
+  Open,
+  Close,
+  Comma,
+}
+
+impl fmt::Display for Token {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Token::Ident(s) => write!(f, "{}", s),
+      Token::Str(s) => write!(f, "'{}'", s),
+      Token::Number(n) => write!(f, "{}", n),
+      Token::Float(n) => write!(f, "{:?}", n),
+      Token::Param(n) => write!(f, "${}", n),
+      Token::Op(op) => write!(f, "{}", op),
+      Token::Open => write!(f, "("),
+      Token::Close => write!(f, ")"),
+      Token::Comma => write!(f, ","),
+    }
+  }
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, Error> {
+  let chars = s.char_indices().collect::<Vec<(usize, char)>>();
+  let mut tokens = vec![];
+  let mut i = 0;
+  while i < chars.len() {
+    let (position, ch) = chars[i];
+    let next = chars.get(i + 1).map(|(_, c)| *c);
+    match ch {
+      c if c.is_whitespace() => { i += 1; continue; },
+      '(' => { tokens.push((position, Token::Open)); i += 1; },
+      ')' => { tokens.push((position, Token::Close)); i += 1; },
+      ',' => { tokens.push((position, Token::Comma)); i += 1; },
+      '=' => { tokens.push((position, Token::Op("="))); i += 1; },
+      '!' if next == Some('=') => { tokens.push((position, Token::Op("!="))); i += 2; },
+      '<' if next == Some('>') => { tokens.push((position, Token::Op("!="))); i += 2; },
+      '<' if next == Some('=') => { tokens.push((position, Token::Op("<="))); i += 2; },
+      '<' => { tokens.push((position, Token::Op("<"))); i += 1; },
+      '>' if next == Some('=') => { tokens.push((position, Token::Op(">="))); i += 2; },
+      '>' => { tokens.push((position, Token::Op(">"))); i += 1; },
+      '~' => { tokens.push((position, Token::Op("~"))); i += 1; },
+      '\'' => {
+        let mut string = String::new();
+        i += 1;
+        loop {
+          match chars.get(i).map(|(_, c)| *c) {
+            Some('\'') if chars.get(i + 1).map(|(_, c)| *c) == Some('\'') => { string.push('\''); i += 2; },
+            Some('\'') => { i += 1; break; },
+            Some(c) => { string.push(c); i += 1; },
+            None => return Err(Error::UnterminatedString { position }),
+          }
+        }
+        tokens.push((position, Token::Str(string)));
+      },
+      '"' => {
+        let mut string = String::new();
+        i += 1;
+        loop {
+          match chars.get(i).map(|(_, c)| *c) {
+            Some('"') => { i += 1; break; },
+            Some(c) => { string.push(c); i += 1; },
+            None => return Err(Error::UnterminatedString { position }),
+          }
+        }
+        tokens.push((position, Token::Ident(string)));
+      },
+      '$' if next.map(|c| c.is_ascii_digit()).unwrap_or(false) => {
+        let mut string = String::new();
+        i += 1;
+        while let Some(c) = chars.get(i).map(|(_, c)| *c).filter(|c| c.is_ascii_digit()) {
+          string.push(c);
+          i += 1;
+        }
+        tokens.push((position, Token::Param(string.parse().map_err(|_| Error::UnexpectedChar { position, found: '$' })?)));
+      },
+      c if c.is_ascii_digit() || (c == '-' && next.map(|c| c.is_ascii_digit()).unwrap_or(false)) => {
+        let mut string = String::new();
+        string.push(c);
+        i += 1;
+        while let Some(c) = chars.get(i).map(|(_, c)| *c).filter(|c| c.is_ascii_digit() || *c == '.') {
+          string.push(c);
+          i += 1;
+        }
+        if string.contains('.') {
+          tokens.push((position, Token::Float(string.parse().map_err(|_| Error::UnexpectedChar { position, found: c })?)));
+        } else {
+          tokens.push((position, Token::Number(string.parse().map_err(|_| Error::UnexpectedChar { position, found: c })?)));
+        }
+      },
+      c if c.is_alphabetic() || c == '_' => {
+        let mut string = String::new();
+        while let Some(c) = chars.get(i).map(|(_, c)| *c).filter(|c| c.is_alphanumeric() || *c == '_' || *c == '.') {
+          string.push(c);
+          i += 1;
+        }
+        tokens.push((position, Token::Ident(string)));
+      },
+      c => return Err(Error::UnexpectedChar { position, found: c }),
+    }
+  }
+  Ok(tokens)
+}
+
+struct Parser<'a> {
+  tokens: Vec<(usize, Token)>,
+  index: usize,
+  params: &'a [Value],
+}
+
+impl<'a> Parser<'a> {
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.index).map(|(_, t)| t)
+  }
+
+  fn next(&mut self) -> Result<(usize, Token), Error> {
+    let token = self.tokens.get(self.index).cloned().ok_or(Error::UnexpectedEnd)?;
+    self.index += 1;
+    Ok(token)
+  }
+
+  fn unexpected(&self) -> Error {
+    match self.tokens.get(self.index) {
+      Some((position, token)) => Error::UnexpectedToken { position: *position, found: token.to_string() },
+      None => Error::UnexpectedEnd,
+    }
+  }
+
+  fn is_keyword(&self, keyword: &str) -> bool {
+    match self.peek() {
+      Some(Token::Ident(ident)) => ident.eq_ignore_ascii_case(keyword),
+      _ => false,
+    }
+  }
+
+  fn keyword(&mut self, keyword: &str) -> bool {
+    let matched = self.is_keyword(keyword);
+    if matched {
+      self.index += 1;
+    }
+    matched
+  }
+
+  fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+    if self.keyword(keyword) {
+      Ok(())
+    } else {
+      Err(self.unexpected())
+    }
+  }
+
+  fn expect(&mut self, token: Token) -> Result<(), Error> {
+    match self.peek() {
+      Some(t) if *t == token => { self.index += 1; Ok(()) },
+      _ => Err(self.unexpected()),
+    }
+  }
+
+  fn or(&mut self) -> Result<Query, Error> {
+    let mut queries = vec![self.and()?];
+    while self.keyword("or") {
+      queries.push(self.and()?);
+    }
+    Ok(Query::any(queries))
+  }
+
+  fn and(&mut self) -> Result<Query, Error> {
+    let mut queries = vec![self.not()?];
+    while self.keyword("and") {
+      queries.push(self.not()?);
+    }
+    Ok(Query::all(queries))
+  }
+
+  fn not(&mut self) -> Result<Query, Error> {
+    if self.keyword("not") {
+      Ok(Query::Not(Box::new(self.not()?)))
+    } else {
+      self.primary()
+    }
+  }
+
+  fn primary(&mut self) -> Result<Query, Error> {
+    if self.peek() == Some(&Token::Open) {
+      self.index += 1;
+      let query = self.or()?;
+      self.expect(Token::Close)?;
+      return Ok(query);
+    }
+    let field = match self.next()? {
+      (_, Token::Ident(field)) => field,
+      _ => { self.index -= 1; return Err(self.unexpected()); },
+    };
+    self.predicate(field)
+  }
+
+  fn predicate(&mut self, field: String) -> Result<Query, Error> {
+    if let Some(Token::Op(op)) = self.peek().cloned() {
+      self.index += 1;
+      let value = self.value()?;
+      return Ok(match op {
+        "=" => Query::Eq { field, value },
+        "!=" => Query::Neq { field, value },
+        ">" => Query::Gt { field, value },
+        ">=" => Query::GtE { field, value },
+        "<" => Query::Lt { field, value },
+        "~" => Query::Rx { field, value },
+        _ => Query::LtE { field, value },
+      });
+    }
+    if self.keyword("is") {
+      let negated = self.keyword("not");
+      self.expect_keyword("null")?;
+      return Ok(if negated {
+        Query::Neq { field, value: Value::None }
+      } else {
+        Query::Eq { field, value: Value::None }
+      });
+    }
+    if self.keyword("contains") {
+      let value = self.value()?;
+      return Ok(Query::Contains { field, value });
+    }
+    let negated = self.keyword("not");
+    let query = if self.keyword("in") {
+      let value = match self.peek() {
+        Some(Token::Open) => self.list()?,
+        _ => self.value()?,
+      };
+      Query::In { field, value }
+    } else if self.keyword("like") {
+      let value = match self.value()? {
+        Value::String(pattern) => Value::String(like_to_regex(&pattern)),
+        value => value,
+      };
+      Query::Rx { field, value }
+    } else if self.keyword("between") {
+      let low = self.value()?;
+      self.expect_keyword("and")?;
+      let high = self.value()?;
+      Query::and(Query::GtE { field: field.clone(), value: low }, Query::LtE { field, value: high })
+    } else {
+      return Err(self.unexpected());
+    };
+    Ok(if negated { Query::Not(Box::new(query)) } else { query })
+  }
+
+  fn list(&mut self) -> Result<Value, Error> {
+    self.expect(Token::Open)?;
+    let mut values = vec![];
+    while self.peek() != Some(&Token::Close) {
+      values.push(self.value()?);
+      if self.peek() == Some(&Token::Comma) {
+        self.index += 1;
+      } else if self.peek() != Some(&Token::Close) {
+        return Err(self.unexpected());
+      }
+    }
+    self.index += 1;
+    Ok(Value::Array(Box::new(values)))
+  }
+
+  fn value(&mut self) -> Result<Value, Error> {
+    match self.next()? {
+      (_, Token::Str(s)) => Ok(Value::String(s)),
+      (_, Token::Number(n)) => Ok(Value::Number(n)),
+      (_, Token::Float(f)) => Ok(Value::Float(f)),
+      (position, Token::Param(index)) => match index.checked_sub(1).and_then(|i| self.params.get(i)) {
+        Some(value) => Ok(value.clone()),
+        None => Err(Error::MissingParam { position, index }),
+      },
+      (_, Token::Ident(ref ident)) if ident.eq_ignore_ascii_case("true") => Ok(Value::Bool(true)),
+      (_, Token::Ident(ref ident)) if ident.eq_ignore_ascii_case("false") => Ok(Value::Bool(false)),
+      (_, Token::Ident(ref ident)) if ident.eq_ignore_ascii_case("null") => Ok(Value::None),
+      _ => { self.index -= 1; Err(self.unexpected()) },
+    }
+  }
+}
+
+// Query::Rx holds a regex everywhere else, so LIKE patterns are translated
+// rather than passed through: `%` and `_` become `.*` and `.`, everything
+// else is literal and the whole value has to match.
+fn like_to_regex(pattern: &str) -> String {
+  let mut regex = String::from("^");
+  let mut chars = pattern.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      '%' => regex.push_str(".*"),
+      '_' => regex.push('.'),
+      '\\' => if let Some(c) = chars.next() {
+        escape(c, &mut regex);
+      },
+      c => escape(c, &mut regex),
+    }
+  }
+  regex.push('$');
+  regex
+}
+
+fn escape(c: char, regex: &mut String) {
+  if "\\.+*?()|[]{}^$".contains(c) {
+    regex.push('\\');
+  }
+  regex.push(c);
+}
+
+pub fn from_str(s: &str, params: &[Value]) -> Result<Query, Error> {
+  let tokens = tokenize(s)?;
+  if tokens.is_empty() {
+    return Ok(Query::None);
+  }
+  let mut parser = Parser { tokens, index: 0, params };
+  let query = parser.or()?;
+  match parser.peek() {
+    None => Ok(query),
+    Some(_) => Err(parser.unexpected()),
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::*;
+  use crate::query::parse::sql;
+
+  #[test]
+  fn sql_where() {
+    let q = sql::from_str("deleted = false AND (age >= 21 OR state IN ('Pending', 'Done')) AND name LIKE $1 AND deleted_at IS NULL", &["jo%".into()]).unwrap();
+    let q_r = Query::And(vec![
+      Query::Eq { field: "deleted".to_owned(), value: false.into() },
+      Query::Or(vec![
+        Query::GtE { field: "age".to_owned(), value: 21.into() },
+        Query::In { field: "state".to_owned(), value: vec!["Pending", "Done"].into() },
+      ]),
+      Query::Rx { field: "name".to_owned(), value: "^jo.*$".into() },
+      Query::Eq { field: "deleted_at".to_owned(), value: Value::None },
+    ]);
+    assert_eq!(q, q_r);
+  }
+
+  #[test]
+  fn sql_precedence_and_negation() {
+    let q = sql::from_str("a = 1 OR b <> 'it''s' AND NOT c BETWEEN 1.5 AND 3 AND d NOT IN (1, 2) AND e IS NOT NULL", &[]).unwrap();
+    let between = Query::And(vec![
+      Query::GtE { field: "c".to_owned(), value: 1.5f64.into() },
+      Query::LtE { field: "c".to_owned(), value: 3.into() },
+    ]);
+    let q_r = Query::Or(vec![
+      Query::Eq { field: "a".to_owned(), value: 1.into() },
+      Query::And(vec![
+        Query::Neq { field: "b".to_owned(), value: "it's".into() },
+        Query::Not(Box::new(between)),
+        Query::Not(Box::new(Query::In { field: "d".to_owned(), value: vec![1i64, 2].into() })),
+        Query::Neq { field: "e".to_owned(), value: Value::None },
+      ]),
+    ]);
+    assert_eq!(q, q_r);
+    assert_eq!(sql::from_str("", &[]), Ok(Query::None));
+  }
+
+  #[test]
+  fn sql_errors() {
+    assert_eq!(sql::from_str("a = $2", &[1.into()]), Err(sql::Error::MissingParam { position: 4, index: 2 }));
+    assert_eq!(sql::from_str("a = 1 AND", &[]), Err(sql::Error::UnexpectedEnd));
+    assert_eq!(sql::from_str("a == 1", &[]), Err(sql::Error::UnexpectedToken { position: 3, found: "=".to_owned() }));
+    assert_eq!(sql::from_str("(a = 1", &[]), Err(sql::Error::UnexpectedEnd));
+    assert_eq!(sql::from_str("a = 'x", &[]), Err(sql::Error::UnterminatedString { position: 4 }));
+    assert_eq!(sql::from_str("a @ 1", &[]), Err(sql::Error::UnexpectedChar { position: 2, found: '@' }));
+    assert_eq!(sql::from_str("a = $99999999999999999999", &[]), Err(sql::Error::UnexpectedChar { position: 4, found: '$' }));
+  }
+
+  #[cfg(feature = "postgres")]
+  #[test]
+  fn sql_roundtrip() {
+    use crate::query;
+    use crate::postgres::ToWhere;
+    let q = Query::or(query!("deleted" == false && "b" in [1, 2]), Query::and(query!("c" contains "x" && "d" == "a%"), query!("f" %% "^jo")));
+    let q = Query::and(q, Query::Not(Box::new(Query::Eq { field: "e".to_owned(), value: Value::None })));
+    let w = q.to_where();
+    let params = w.params().iter().map(|p| p.value().clone()).collect::<Vec<Value>>();
+    assert_eq!(sql::from_str(&w.to_string(), &params), Ok(q));
+  }
+
+  #[test]
+  fn sql_like() {
+    let rx = |value: &str| Ok(Query::Rx { field: "name".to_owned(), value: value.into() });
+    assert_eq!(sql::from_str("name LIKE 'jo%'", &[]), rx("^jo.*$"));
+    assert_eq!(sql::from_str("name LIKE '_a.b(c)%'", &[]), rx("^.a\\.b\\(c\\).*$"));
+    assert_eq!(sql::from_str("name LIKE '100\\%'", &[]), rx("^100%$"));
+    assert_eq!(sql::from_str("name LIKE $1", &["a+b".into()]), rx("^a\\+b$"));
+  }
+}
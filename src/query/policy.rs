@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::query::*;
+use crate::query::schema::Operator;
+
+#[derive(Debug, Clone, PartialEq)]
+struct FieldRule {
+  path: String,
+  operators: Option<Vec<Operator>>,
+}
+
+// Guards filters that come from outside, typically through parse::from_str.
+// Only allowed fields may appear and each may be exposed under a different
+// name than the path it is stored at, so the public names are all a client
+// ever sees.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Policy {
+  fields: HashMap<String, FieldRule>,
+  max_depth: Option<usize>,
+  max_nodes: Option<usize>,
+  max_in: Option<usize>,
+  max_regex: Option<usize>,
+  simplify: bool,
+}
+
+impl Policy {
+  pub fn new() -> Policy {
+    Policy::default()
+  }
+  pub fn allow(mut self, field: &str) -> Policy {
+    self.fields.insert(field.to_owned(), FieldRule { path: field.to_owned(), operators: None });
+    self
+  }
+  pub fn allow_operators(mut self, field: &str, operators: &[Operator]) -> Policy {
+    let rule = self.fields.entry(field.to_owned()).or_insert_with(|| FieldRule { path: field.to_owned(), operators: None });
+    rule.operators = Some(operators.to_vec());
+    self
+  }
+  pub fn alias(mut self, field: &str, path: &str) -> Policy {
+    let rule = self.fields.entry(field.to_owned()).or_insert_with(|| FieldRule { path: field.to_owned(), operators: None });
+    rule.path = path.to_owned();
+    self
+  }
+  pub fn max_depth(mut self, max: usize) -> Policy {
+    self.max_depth = Some(max);
+    self
+  }
+  pub fn max_nodes(mut self, max: usize) -> Policy {
+    self.max_nodes = Some(max);
+    self
+  }
+  pub fn max_in(mut self, max: usize) -> Policy {
+    self.max_in = Some(max);
+    self
+  }
+  pub fn max_regex(mut self, max: usize) -> Policy {
+    self.max_regex = Some(max);
+    self
+  }
+  // Runs Query::simplify before the limits are checked, so redundant
+  // nesting and duplicates do not count against them.
+  pub fn simplify(mut self) -> Policy {
+    self.simplify = true;
+    self
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyError {
+  FieldNotAllowed { field: String },
+  OperatorNotAllowed { field: String, operator: Operator },
+  TooDeep { max: usize },
+  TooManyNodes { max: usize },
+  InTooLong { field: String, max: usize },
+  RegexTooLong { field: String, max: usize },
+  RegexTooComplex { field: String },
+}
+
+impl fmt::Display for PolicyError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PolicyError::FieldNotAllowed { field } => write!(f, "filtering on `{}` is not allowed", field),
+      PolicyError::OperatorNotAllowed { field, operator } => write!(f, "operator `{}` is not allowed on `{}`", operator, field),
+      PolicyError::TooDeep { max } => write!(f, "filter is nested more than {} levels deep", max),
+      PolicyError::TooManyNodes { max } => write!(f, "filter has more than {} terms", max),
+      PolicyError::InTooLong { field, max } => write!(f, "`in` on `{}` lists more than {} values", field, max),
+      PolicyError::RegexTooLong { field, max } => write!(f, "regex on `{}` is longer than {} characters", field, max),
+      PolicyError::RegexTooComplex { field } => write!(f, "regex on `{}` repeats a group that already repeats", field),
+    }
+  }
+}
+
+impl std::error::Error for PolicyError {}
+
+fn depth(query: &Query) -> usize {
+  match query {
+    Query::And(queries) | Query::Or(queries) => 1 + queries.iter().map(depth).max().unwrap_or(0),
+    Query::Not(query) => 1 + depth(query),
+    _ => 1,
+  }
+}
+
+fn nodes(query: &Query) -> usize {
+  match query {
+    Query::And(queries) | Query::Or(queries) => 1 + queries.iter().map(nodes).sum::<usize>(),
+    Query::Not(query) => 1 + nodes(query),
+    _ => 1,
+  }
+}
+
+// Patterns like `(a+)+` backtrack exponentially on engines without linear
+// time guarantees, so a repeated group may not contain a repetition itself.
+// Alternatives count too, since `(a|a)*` backtracks the same way whenever
+// two branches can match the same text.
+fn nested_quantifier(pattern: &str) -> bool {
+  let chars = pattern.chars().collect::<Vec<char>>();
+  let mut groups = vec![false];
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '\\' => i += 1,
+      '[' => {
+        i += 1;
+        while i < chars.len() && chars[i] != ']' {
+          if chars[i] == '\\' {
+            i += 1;
+          }
+          i += 1;
+        }
+      },
+      '(' => {
+        groups.push(false);
+        if chars.get(i + 1) == Some(&'?') {
+          i += 1;
+        }
+      },
+      ')' if groups.len() > 1 => {
+        let repeats = groups.pop().unwrap();
+        if repeats && matches!(chars.get(i + 1), Some('*') | Some('+') | Some('{')) {
+          return true;
+        }
+        *groups.last_mut().unwrap() |= repeats;
+      },
+      '*' | '+' | '{' | '|' => *groups.last_mut().unwrap() = true,
+      _ => {},
+    }
+    i += 1;
+  }
+  false
+}
+
+impl Policy {
+  fn check(&self, query: &mut Query, errors: &mut Vec<PolicyError>) {
+    let operator = match Operator::of(query) {
+      Some(operator) => operator,
+      None => return,
+    };
+    let (field, value) = match query.predicate_mut() {
+      Some(predicate) => predicate,
+      None => return,
+    };
+    let rule = match self.fields.get(field.as_str()) {
+      Some(rule) => rule,
+      None => {
+        errors.push(PolicyError::FieldNotAllowed { field: field.clone() });
+        return;
+      },
+    };
+    if rule.operators.as_ref().is_some_and(|operators| !operators.contains(&operator)) {
+      errors.push(PolicyError::OperatorNotAllowed { field: field.clone(), operator });
+      return;
+    }
+    match (operator, &*value) {
+      (Operator::In, Value::Array(values)) => if let Some(max) = self.max_in.filter(|max| values.len() > *max) {
+        errors.push(PolicyError::InTooLong { field: field.clone(), max });
+      },
+      (Operator::Rx, Value::String(pattern)) => {
+        if let Some(max) = self.max_regex.filter(|max| pattern.chars().count() > *max) {
+          errors.push(PolicyError::RegexTooLong { field: field.clone(), max });
+        } else if nested_quantifier(pattern) {
+          errors.push(PolicyError::RegexTooComplex { field: field.clone() });
+        }
+      },
+      _ => {},
+    }
+    *field = rule.path.clone();
+  }
+
+  // Reports every violation at once. On success the returned query has its
+  // field names replaced by the aliased paths and is safe to hand to a
+  // backend.
+  pub fn apply(&self, query: Query) -> Result<Query, Vec<PolicyError>> {
+    let mut query = match self.simplify {
+      true => query.simplify(),
+      false => query,
+    };
+    let mut errors = vec![];
+    if let Some(max) = self.max_depth.filter(|max| depth(&query) > *max) {
+      errors.push(PolicyError::TooDeep { max });
+    }
+    if let Some(max) = self.max_nodes.filter(|max| nodes(&query) > *max) {
+      errors.push(PolicyError::TooManyNodes { max });
+    }
+    query.walk_mut(&mut |query| self.check(query, &mut errors));
+    match errors.is_empty() {
+      true => Ok(query),
+      false => Err(errors),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::policy::{self, *};
+  use crate::query;
+
+  fn policy() -> Policy {
+    Policy::new()
+      .allow("age")
+      .allow("name")
+      .allow_operators("id", &[Operator::Eq, Operator::In])
+      .alias("created", "meta.created_at")
+      .max_depth(3)
+      .max_nodes(8)
+      .max_in(3)
+      .max_regex(10)
+  }
+
+  #[test]
+  fn policy_allows_and_rewrites() {
+    let q = Query::And(vec![
+      query!("age" >= 21),
+      Query::Or(vec![query!("name" %% "^jo(hn)?"), query!("created" > 100)]),
+      Query::In { field: "id".to_owned(), value: vec![1, 2, 3].into() },
+    ]);
+    let q_r = Query::And(vec![
+      query!("age" >= 21),
+      Query::Or(vec![query!("name" %% "^jo(hn)?"), query!("meta.created_at" > 100)]),
+      Query::In { field: "id".to_owned(), value: vec![1, 2, 3].into() },
+    ]);
+    assert_eq!(policy().apply(q), Ok(q_r));
+  }
+
+  #[test]
+  fn policy_rejects() {
+    let q = Query::And(vec![
+      query!("secret" == 1),
+      query!("id" > 5),
+      Query::In { field: "id".to_owned(), value: vec![1, 2, 3, 4].into() },
+      query!("name" %% "^[a-z]+@[a-z]+\\.com$"),
+      query!("name" %% "(a+)+$"),
+    ]);
+    let field = |f: &str| f.to_owned();
+    assert_eq!(policy().apply(q), Err(vec![
+      PolicyError::FieldNotAllowed { field: field("secret") },
+      PolicyError::OperatorNotAllowed { field: field("id"), operator: Operator::Gt },
+      PolicyError::InTooLong { field: field("id"), max: 3 },
+      PolicyError::RegexTooLong { field: field("name"), max: 10 },
+      PolicyError::RegexTooComplex { field: field("name") },
+    ]));
+    let q = Query::Not(Box::new(Query::Or(vec![query!("age" == 1), Query::Not(Box::new(query!("age" == 2 && "name" == "a")))])));
+    assert_eq!(policy().apply(q), Err(vec![PolicyError::TooDeep { max: 3 }]));
+    let q = Query::all((0..8).map(|i| query!("age" == i)));
+    assert_eq!(policy().apply(q), Err(vec![PolicyError::TooManyNodes { max: 8 }]));
+    assert_eq!(PolicyError::TooManyNodes { max: 8 }.to_string(), "filter has more than 8 terms");
+  }
+
+  #[test]
+  fn policy_simplifies_first() {
+    let q = Query::And(vec![query!("age" == 1 && "age" == 1), Query::Or(vec![query!("age" == 1 || "age" == 1)])]);
+    assert_eq!(policy().max_nodes(2).apply(q.clone()), Err(vec![PolicyError::TooDeep { max: 3 }, PolicyError::TooManyNodes { max: 2 }]));
+    assert_eq!(policy().max_nodes(2).simplify().apply(q), Ok(query!("age" == 1)));
+  }
+
+  #[test]
+  fn regex_complexity() {
+    for pattern in ["(a+)+", "(a*)*b", "((ab)+c)+", "(?:x+y)+", "(.*){2,}", "(a|a)*", "(a|aa)+$", "((a|b))+"] {
+      assert!(policy::nested_quantifier(pattern), "{}", pattern);
+    }
+    for pattern in ["^a+b*$", "(ab)+", "(a+)?", "[(a+)]+", "\\(a+\\)+", "(?i)jo+", "(a|b)?", "a|b+", "[a|b]+"] {
+      assert!(!policy::nested_quantifier(pattern), "{}", pattern);
+    }
+  }
+}
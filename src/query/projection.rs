@@ -0,0 +1,28 @@
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Projection {
+  pub include: Vec<String>,
+  pub exclude: Vec<String>,
+}
+
+impl Projection {
+  pub fn new() -> Projection {
+    Projection::default()
+  }
+  pub fn include(mut self, field: &str) -> Projection {
+    self.include.push(field.to_owned());
+    self
+  }
+  pub fn exclude(mut self, field: &str) -> Projection {
+    self.exclude.push(field.to_owned());
+    self
+  }
+  pub fn is_empty(&self) -> bool {
+    self.include.is_empty() && self.exclude.is_empty()
+  }
+}
+
+impl From<Vec<&str>> for Projection {
+  fn from(fields: Vec<&str>) -> Projection {
+    Projection { include: fields.into_iter().map(|f| f.to_owned()).collect(), exclude: vec![] }
+  }
+}
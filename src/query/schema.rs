@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::query::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+  Uuid,
+  Number,
+  Float,
+  String,
+  Bool,
+}
+
+impl fmt::Display for ValueType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValueType::Uuid => write!(f, "uuid"),
+      ValueType::Number => write!(f, "number"),
+      ValueType::Float => write!(f, "float"),
+      ValueType::String => write!(f, "string"),
+      ValueType::Bool => write!(f, "bool"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+  Eq,
+  Neq,
+  Gt,
+  GtE,
+  Lt,
+  LtE,
+  Rx,
+  In,
+  Contains,
+}
+
+impl Operator {
+  pub fn of(query: &Query) -> Option<Operator> {
+    match query {
+      Query::Eq { .. } => Some(Operator::Eq),
+      Query::Neq { .. } => Some(Operator::Neq),
+      Query::Gt { .. } => Some(Operator::Gt),
+      Query::GtE { .. } => Some(Operator::GtE),
+      Query::Lt { .. } => Some(Operator::Lt),
+      Query::LtE { .. } => Some(Operator::LtE),
+      Query::Rx { .. } => Some(Operator::Rx),
+      Query::In { .. } => Some(Operator::In),
+      Query::Contains { .. } => Some(Operator::Contains),
+      _ => None,
+    }
+  }
+  pub fn query(self, field: String, value: Value) -> Query {
+    match self {
+      Operator::Eq => Query::Eq { field, value },
+      Operator::Neq => Query::Neq { field, value },
+      Operator::Gt => Query::Gt { field, value },
+      Operator::GtE => Query::GtE { field, value },
+      Operator::Lt => Query::Lt { field, value },
+      Operator::LtE => Query::LtE { field, value },
+      Operator::Rx => Query::Rx { field, value },
+      Operator::In => Query::In { field, value },
+      Operator::Contains => Query::Contains { field, value },
+    }
+  }
+}
+
+// Written the way parse::from_str reads them, since that is where the
+// filters being reported on usually come from.
+impl fmt::Display for Operator {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Operator::Eq => write!(f, "=="),
+      Operator::Neq => write!(f, "!="),
+      Operator::Gt => write!(f, ">"),
+      Operator::GtE => write!(f, ">="),
+      Operator::Lt => write!(f, "<"),
+      Operator::LtE => write!(f, "<="),
+      Operator::Rx => write!(f, "#="),
+      Operator::In => write!(f, "in"),
+      Operator::Contains => write!(f, "contains"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+  pub value_type: ValueType,
+  pub nullable: bool,
+  pub array: bool,
+  pub operators: Option<Vec<Operator>>,
+}
+
+impl FieldSchema {
+  pub fn new(value_type: ValueType) -> FieldSchema {
+    FieldSchema { value_type, nullable: false, array: false, operators: None }
+  }
+  pub fn nullable(mut self) -> FieldSchema {
+    self.nullable = true;
+    self
+  }
+  pub fn array(mut self) -> FieldSchema {
+    self.array = true;
+    self
+  }
+  pub fn operators(mut self, operators: &[Operator]) -> FieldSchema {
+    self.operators = Some(operators.to_vec());
+    self
+  }
+  // Without an explicit list a field allows whatever makes sense for its
+  // type: regex only on strings, ordering only on numbers and strings and
+  // contains only on arrays.
+  pub fn allows(&self, operator: Operator) -> bool {
+    if operator == Operator::Contains && !self.array {
+      return false;
+    }
+    match &self.operators {
+      Some(operators) => operators.contains(&operator),
+      None => match operator {
+        Operator::Eq | Operator::Neq | Operator::In | Operator::Contains => true,
+        Operator::Gt | Operator::GtE | Operator::Lt | Operator::LtE => {
+          matches!(self.value_type, ValueType::Number | ValueType::Float | ValueType::String)
+        },
+        Operator::Rx => self.value_type == ValueType::String,
+      },
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+  fields: HashMap<String, FieldSchema>,
+}
+
+impl Schema {
+  pub fn new() -> Schema {
+    Schema::default()
+  }
+  pub fn field(mut self, path: &str, field: FieldSchema) -> Schema {
+    self.fields.insert(path.to_owned(), field);
+    self
+  }
+  pub fn nested(mut self, path: &str, schema: Schema) -> Schema {
+    for (name, field) in schema.fields {
+      self.fields.insert(format!("{}.{}", path, name), field);
+    }
+    self
+  }
+  pub fn get(&self, path: &str) -> Option<&FieldSchema> {
+    self.fields.get(path)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+  UnknownField { field: String },
+  DisallowedOperator { field: String, operator: Operator },
+  TypeMismatch { field: String, expected: ValueType, found: &'static str },
+  NotNullable { field: String },
+}
+
+impl fmt::Display for ValidationError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValidationError::UnknownField { field } => write!(f, "unknown field `{}`", field),
+      ValidationError::DisallowedOperator { field, operator } => write!(f, "operator `{}` is not allowed on `{}`", operator, field),
+      ValidationError::TypeMismatch { field, expected, found } => write!(f, "`{}` expects a {} but got a {}", field, expected, found),
+      ValidationError::NotNullable { field } => write!(f, "`{}` cannot be null", field),
+    }
+  }
+}
+
+impl std::error::Error for ValidationError {}
+
+fn describe(value: &Value) -> &'static str {
+  match value {
+    Value::Uuid(_) => "uuid",
+    Value::Number(_) => "number",
+    Value::Float(_) => "float",
+    Value::String(_) => "string",
+    Value::Bool(_) => "bool",
+    Value::Array(_) => "array",
+    Value::None => "null",
+  }
+}
+
+// Rewrites `value` into the field's type where that loses nothing, which is
+// how uuids get into filters given that the parser only produces strings.
+// Integers past 2^53 would be rounded on the way to f64, so they are reported
+// as a mismatch instead.
+fn coerce(value: &mut Value, value_type: ValueType) -> bool {
+  let coerced = match (value_type, &*value) {
+    (ValueType::Uuid, Value::Uuid(_)) | (ValueType::Number, Value::Number(_)) | (ValueType::Float, Value::Float(_))
+      | (ValueType::String, Value::String(_)) | (ValueType::Bool, Value::Bool(_)) => return true,
+    (ValueType::Uuid, Value::String(s)) => match Uuid::parse_str(s) {
+      Ok(uuid) => Value::Uuid(uuid),
+      Err(_) => return false,
+    },
+    (ValueType::Float, Value::Number(n)) if n.unsigned_abs() <= 1 << 53 => Value::Float(*n as f64),
+    _ => return false,
+  };
+  *value = coerced;
+  true
+}
+
+fn check(field: &str, schema: &FieldSchema, operator: Operator, value: &mut Value, errors: &mut Vec<ValidationError>) {
+  let mismatch = |expected, value: &Value| ValidationError::TypeMismatch { field: field.to_owned(), expected, found: describe(value) };
+  if !schema.allows(operator) {
+    errors.push(ValidationError::DisallowedOperator { field: field.to_owned(), operator });
+    return;
+  }
+  match (operator, value) {
+    (Operator::Eq, Value::None) | (Operator::Neq, Value::None) => if !schema.nullable {
+      errors.push(ValidationError::NotNullable { field: field.to_owned() });
+    },
+    (Operator::Rx, Value::String(_)) => {},
+    (Operator::Rx, value) => errors.push(mismatch(ValueType::String, value)),
+    // In takes a list of candidates, and equality on an array field may
+    // compare the whole array, so both check element by element.
+    (_, Value::Array(values)) if operator == Operator::In || (schema.array && operator != Operator::Contains) => for value in values.iter_mut() {
+      if !coerce(value, schema.value_type) {
+        errors.push(mismatch(schema.value_type, value));
+      }
+    },
+    (_, value) => if !coerce(value, schema.value_type) {
+      errors.push(mismatch(schema.value_type, value));
+    },
+  }
+}
+
+impl Query {
+  // Checks every predicate against `schema`, collecting all problems rather
+  // than stopping at the first. Compatible literals are coerced in place, so
+  // a query that validates is ready to hand to a backend.
+  pub fn validate(&mut self, schema: &Schema) -> Result<(), Vec<ValidationError>> {
+    let mut errors = vec![];
+    self.walk_mut(&mut |query| {
+      let operator = match Operator::of(query) {
+        Some(operator) => operator,
+        None => return,
+      };
+      if let Some((field, value)) = query.predicate_mut() {
+        match schema.get(field) {
+          Some(field_schema) => check(field, field_schema, operator, value, &mut errors),
+          None => errors.push(ValidationError::UnknownField { field: field.clone() }),
+        }
+      }
+    });
+    match errors.is_empty() {
+      true => Ok(()),
+      false => Err(errors),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::schema::*;
+  use crate::query;
+
+  fn schema() -> Schema {
+    let account = Schema::new()
+      .field("id", FieldSchema::new(ValueType::Uuid).operators(&[Operator::Eq, Operator::In]))
+      .field("currency_iso", FieldSchema::new(ValueType::String));
+    Schema::new()
+      .field("_id", FieldSchema::new(ValueType::Uuid))
+      .field("age", FieldSchema::new(ValueType::Number))
+      .field("amount", FieldSchema::new(ValueType::Float))
+      .field("name", FieldSchema::new(ValueType::String))
+      .field("state", FieldSchema::new(ValueType::String).nullable())
+      .field("deleted", FieldSchema::new(ValueType::Bool))
+      .field("tags", FieldSchema::new(ValueType::String).array())
+      .nested("source", account)
+  }
+
+  #[test]
+  fn validate_accepts_and_coerces() {
+    let uuid = uuid::Uuid::new_v4();
+    let id = uuid.hyphenated().to_string();
+    let mut q = Query::And(vec![
+      query!("_id" == { id.clone() }),
+      query!("amount" > 10),
+      query!("amount" <= 9007199254740992i64),
+      query!("name" %% "^jo"),
+      Query::Eq { field: "state".to_owned(), value: Value::None },
+      query!("tags" contains "za"),
+      Query::In { field: "source.id".to_owned(), value: vec![id.clone()].into() },
+    ]);
+    assert_eq!(q.validate(&schema()), Ok(()));
+    let q_r = Query::And(vec![
+      query!("_id" == uuid),
+      query!("amount" > 10.0f64),
+      query!("amount" <= 9007199254740992.0f64),
+      query!("name" %% "^jo"),
+      Query::Eq { field: "state".to_owned(), value: Value::None },
+      query!("tags" contains "za"),
+      Query::In { field: "source.id".to_owned(), value: Value::Array(Box::new(vec![uuid.into()])) },
+    ]);
+    assert_eq!(q, q_r);
+  }
+
+  #[test]
+  fn validate_reports_every_error() {
+    let mut q = Query::And(vec![
+      query!("age" == "abc"),
+      query!("unknown" == 1),
+      query!("_id" %% "^12"),
+      query!("source.id" != "not-a-uuid"),
+      query!("deleted" > true),
+      query!("name" contains "a"),
+      Query::Eq { field: "age".to_owned(), value: Value::None },
+      Query::In { field: "age".to_owned(), value: Value::Array(Box::new(vec![Value::Number(1), Value::Bool(true)])) },
+      Query::Not(Box::new(query!("_id" == "nope"))),
+      query!("amount" == 9007199254740993i64),
+    ]);
+    let errors = q.validate(&schema()).unwrap_err();
+    let field = |f: &str| f.to_owned();
+    assert_eq!(errors, vec![
+      ValidationError::TypeMismatch { field: field("age"), expected: ValueType::Number, found: "string" },
+      ValidationError::UnknownField { field: field("unknown") },
+      ValidationError::DisallowedOperator { field: field("_id"), operator: Operator::Rx },
+      ValidationError::DisallowedOperator { field: field("source.id"), operator: Operator::Neq },
+      ValidationError::DisallowedOperator { field: field("deleted"), operator: Operator::Gt },
+      ValidationError::DisallowedOperator { field: field("name"), operator: Operator::Contains },
+      ValidationError::NotNullable { field: field("age") },
+      ValidationError::TypeMismatch { field: field("age"), expected: ValueType::Number, found: "bool" },
+      ValidationError::TypeMismatch { field: field("_id"), expected: ValueType::Uuid, found: "string" },
+      ValidationError::TypeMismatch { field: field("amount"), expected: ValueType::Float, found: "number" },
+    ]);
+    assert_eq!(errors[0].to_string(), "`age` expects a number but got a string");
+    assert_eq!(errors[2].to_string(), "operator `#=` is not allowed on `_id`");
+  }
+}
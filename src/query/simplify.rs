@@ -0,0 +1,354 @@
+use std::cmp::Ordering;
+
+use crate::query::*;
+
+// Query::None matches everything, so it doubles as the constant true;
+// NOT of it is the constant false that contradictions fold into.
+pub(crate) fn falsity() -> Query {
+  Query::Not(Box::new(Query::None))
+}
+
+impl Query {
+  pub fn is_false(&self) -> bool {
+    matches!(self, Query::Not(query) if **query == Query::None)
+  }
+  pub fn simplify(self) -> Query {
+    Simplifier.fold(self)
+  }
+}
+
+enum Merge {
+  Keep,
+  Into(Query),
+  Const,
+}
+
+// Integers past 2^53 do not survive the trip to f64, so they are not
+// compared with floats at all.
+fn exact(n: i64) -> Option<f64> {
+  match n.unsigned_abs() <= 1 << 53 {
+    true => Some(n as f64),
+    false => None,
+  }
+}
+
+// Strings are ordered by the column's collation in SQL backends, which need
+// not agree with byte order, so only equal strings count as ordered.
+pub(crate) fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+  match (a, b) {
+    (Value::Number(a), Value::Number(b)) => Some(a.cmp(b)),
+    (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+    (Value::Number(a), Value::Float(b)) => exact(*a)?.partial_cmp(b),
+    (Value::Float(a), Value::Number(b)) => a.partial_cmp(&exact(*b)?),
+    (Value::String(a), Value::String(b)) if a == b => Some(Ordering::Equal),
+    (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+    (Value::Uuid(a), Value::Uuid(b)) => Some(a.cmp(b)),
+    _ => None,
+  }
+}
+
+// Equality does not depend on the collation, so strings can be told apart
+// here even though compare leaves them unordered.
+pub(crate) fn equal(a: &Value, b: &Value) -> Option<bool> {
+  match (a, b) {
+    (Value::String(a), Value::String(b)) => Some(a == b),
+    _ => compare(a, b).map(|o| o == Ordering::Equal),
+  }
+}
+
+pub(crate) fn satisfies(value: &Value, bound: &Query) -> Option<bool> {
+  match bound {
+    Query::Gt { value: b, .. } => compare(value, b).map(|o| o == Ordering::Greater),
+    Query::GtE { value: b, .. } => compare(value, b).map(|o| o != Ordering::Less),
+    Query::Lt { value: b, .. } => compare(value, b).map(|o| o == Ordering::Less),
+    Query::LtE { value: b, .. } => compare(value, b).map(|o| o != Ordering::Greater),
+    _ => None,
+  }
+}
+
+pub(crate) fn member(value: &Value, values: &[Value]) -> Option<bool> {
+  let found = values.iter().map(|v| equal(value, v)).collect::<Vec<Option<bool>>>();
+  if found.contains(&Some(true)) {
+    Some(true)
+  } else if found.iter().all(Option::is_some) {
+    Some(false)
+  } else {
+    None
+  }
+}
+
+fn scalar(value: &Value) -> bool {
+  !matches!(value, Value::None | Value::Array(_))
+}
+
+fn values(values: Vec<Value>, field: &str) -> Query {
+  match values.len() {
+    1 => Query::Eq { field: field.to_owned(), value: values.into_iter().next().unwrap() },
+    _ => Query::In { field: field.to_owned(), value: Value::Array(Box::new(values)) },
+  }
+}
+
+fn is_lower(query: &Query) -> bool {
+  matches!(query, Query::Gt { .. } | Query::GtE { .. })
+}
+
+fn is_upper(query: &Query) -> bool {
+  matches!(query, Query::Lt { .. } | Query::LtE { .. })
+}
+
+fn negates(p: &Query, q: &Query) -> bool {
+  matches!(p, Query::Not(inner) if **inner == *q) || matches!(q, Query::Not(inner) if **inner == *p)
+}
+
+fn and_ordered(p: &Query, q: &Query) -> Merge {
+  let (field, pv, qv) = match (p.predicate(), q.predicate()) {
+    (Some((pf, pv)), Some((qf, qv))) if pf == qf => (pf, pv, qv),
+    _ => return Merge::Keep,
+  };
+  match (p, q) {
+    (Query::Eq { .. }, Query::Eq { .. }) => match equal(pv, qv) {
+      Some(true) => Merge::Into(p.clone()),
+      Some(false) => Merge::Const,
+      None => Merge::Keep,
+    },
+    (Query::Eq { .. }, Query::Neq { .. }) => match equal(pv, qv) {
+      Some(true) => Merge::Const,
+      Some(false) => Merge::Into(p.clone()),
+      None => Merge::Keep,
+    },
+    (Query::Eq { .. }, bound) if is_lower(bound) || is_upper(bound) => match satisfies(pv, bound) {
+      Some(true) => Merge::Into(p.clone()),
+      Some(false) => Merge::Const,
+      None => Merge::Keep,
+    },
+    (Query::Eq { .. }, Query::In { value: Value::Array(arr), .. }) => match member(pv, arr) {
+      Some(true) => Merge::Into(p.clone()),
+      Some(false) => Merge::Const,
+      None => Merge::Keep,
+    },
+    (Query::In { value: Value::Array(a), .. }, Query::In { value: Value::Array(b), .. }) => {
+      if a.iter().chain(b.iter()).any(|v| !scalar(v)) {
+        return Merge::Keep;
+      }
+      let both = a.iter().filter(|v| member(v, b) == Some(true)).cloned().collect::<Vec<Value>>();
+      match both.len() {
+        0 => Merge::Const,
+        _ => Merge::Into(values(both, field)),
+      }
+    },
+    (lower, other) if is_lower(lower) && is_lower(other) => match compare(pv, qv) {
+      Some(Ordering::Greater) => Merge::Into(p.clone()),
+      Some(Ordering::Less) => Merge::Into(q.clone()),
+      Some(Ordering::Equal) if matches!(p, Query::Gt { .. }) => Merge::Into(p.clone()),
+      Some(Ordering::Equal) => Merge::Into(q.clone()),
+      None => Merge::Keep,
+    },
+    (upper, other) if is_upper(upper) && is_upper(other) => match compare(pv, qv) {
+      Some(Ordering::Less) => Merge::Into(p.clone()),
+      Some(Ordering::Greater) => Merge::Into(q.clone()),
+      Some(Ordering::Equal) if matches!(p, Query::Lt { .. }) => Merge::Into(p.clone()),
+      Some(Ordering::Equal) => Merge::Into(q.clone()),
+      None => Merge::Keep,
+    },
+    (lower, upper) if is_lower(lower) && is_upper(upper) => match compare(pv, qv) {
+      Some(Ordering::Greater) => Merge::Const,
+      Some(Ordering::Equal) if matches!((p, q), (Query::GtE { .. }, Query::LtE { .. })) => Merge::Into(Query::Eq { field: field.clone(), value: pv.clone() }),
+      Some(Ordering::Equal) => Merge::Const,
+      _ => Merge::Keep,
+    },
+    _ => Merge::Keep,
+  }
+}
+
+fn or_ordered(p: &Query, q: &Query) -> Merge {
+  let (field, pv, qv) = match (p.predicate(), q.predicate()) {
+    (Some((pf, pv)), Some((qf, qv))) if pf == qf => (pf, pv, qv),
+    _ => return Merge::Keep,
+  };
+  match (p, q) {
+    (Query::Eq { .. }, Query::Eq { .. }) if scalar(pv) && scalar(qv) => Merge::Into(values(vec![pv.clone(), qv.clone()], field)),
+    (Query::Eq { .. }, Query::In { value: Value::Array(arr), .. }) if scalar(pv) => match member(pv, arr) {
+      Some(true) => Merge::Into(q.clone()),
+      _ => Merge::Into(values(Some(pv.clone()).into_iter().chain(arr.iter().cloned()).collect(), field)),
+    },
+    (Query::In { value: Value::Array(arr), .. }, Query::Eq { .. }) if scalar(qv) => match member(qv, arr) {
+      Some(true) => Merge::Into(p.clone()),
+      _ => Merge::Into(values(arr.iter().cloned().chain(Some(qv.clone())).collect(), field)),
+    },
+    (Query::In { value: Value::Array(a), .. }, Query::In { value: Value::Array(b), .. }) => {
+      let extra = b.iter().filter(|v| !a.contains(v)).cloned();
+      Merge::Into(values(a.iter().cloned().chain(extra).collect(), field))
+    },
+    (Query::Eq { .. }, bound) if is_lower(bound) || is_upper(bound) => match satisfies(pv, bound) {
+      Some(true) => Merge::Into(q.clone()),
+      _ => Merge::Keep,
+    },
+    (lower, other) if is_lower(lower) && is_lower(other) => match compare(pv, qv) {
+      Some(Ordering::Less) => Merge::Into(p.clone()),
+      Some(Ordering::Greater) => Merge::Into(q.clone()),
+      Some(Ordering::Equal) if matches!(p, Query::GtE { .. }) => Merge::Into(p.clone()),
+      Some(Ordering::Equal) => Merge::Into(q.clone()),
+      None => Merge::Keep,
+    },
+    (upper, other) if is_upper(upper) && is_upper(other) => match compare(pv, qv) {
+      Some(Ordering::Greater) => Merge::Into(p.clone()),
+      Some(Ordering::Less) => Merge::Into(q.clone()),
+      Some(Ordering::Equal) if matches!(p, Query::LtE { .. }) => Merge::Into(p.clone()),
+      Some(Ordering::Equal) => Merge::Into(q.clone()),
+      None => Merge::Keep,
+    },
+    _ => Merge::Keep,
+  }
+}
+
+fn chain(mut queries: Vec<Query>, and: bool) -> Query {
+  match queries.len() {
+    0 if and => Query::None,
+    0 => falsity(),
+    1 => queries.pop().unwrap(),
+    _ if and => Query::And(queries),
+    _ => Query::Or(queries),
+  }
+}
+
+// Merges the operands of a flattened AND or OR pairwise until nothing else
+// combines. A merged pair goes back on the work list so that it can meet the
+// remaining operands again, e.g. a > 1 && a < 10 && a > 20, and keeps the
+// position of the operand it was merged into.
+fn combine(queries: Vec<Query>, and: bool) -> Query {
+  let merge = if and { and_ordered } else { or_ordered };
+  let absorbing = |q: &Query| if and { q.is_false() } else { *q == Query::None };
+  let identity = |q: &Query| if and { *q == Query::None } else { q.is_false() };
+  let constant = || if and { falsity() } else { Query::None };
+  let mut operands = vec![];
+  for query in &queries {
+    flatten(query, and, &mut operands);
+  }
+  let mut pending = operands.into_iter().rev().map(|q| (q.clone(), None)).collect::<Vec<(Query, Option<usize>)>>();
+  let mut out: Vec<Option<Query>> = vec![];
+  'next: while let Some((q, slot)) = pending.pop() {
+    if absorbing(&q) {
+      return constant();
+    }
+    if identity(&q) || out.iter().flatten().any(|p| *p == q) {
+      continue;
+    }
+    for (i, p) in out.iter_mut().enumerate() {
+      let merged = match p {
+        Some(p) if negates(p, &q) => Merge::Const,
+        Some(p) => match merge(p, &q) {
+          Merge::Keep => merge(&q, p),
+          merged => merged,
+        },
+        None => Merge::Keep,
+      };
+      match merged {
+        Merge::Keep => {},
+        Merge::Into(query) => {
+          *p = None;
+          pending.push((query, Some(slot.map_or(i, |s| s.min(i)))));
+          continue 'next;
+        },
+        Merge::Const => return constant(),
+      }
+    }
+    match slot {
+      Some(i) => out[i] = Some(q),
+      None => out.push(Some(q)),
+    }
+  }
+  chain(out.into_iter().flatten().collect(), and)
+}
+
+struct Simplifier;
+
+impl QueryFolder for Simplifier {
+  fn fold_and(&mut self, queries: Vec<Query>) -> Query {
+    combine(queries, true)
+  }
+  fn fold_or(&mut self, queries: Vec<Query>) -> Query {
+    combine(queries, false)
+  }
+  fn fold_not(&mut self, query: Query) -> Query {
+    match query {
+      Query::Not(query) => *query,
+      query => Query::Not(Box::new(query)),
+    }
+  }
+  fn fold_in(&mut self, field: String, value: Value) -> Query {
+    match value {
+      Value::Array(arr) if arr.is_empty() => falsity(),
+      Value::Array(arr) if arr.len() == 1 => Query::Eq { field, value: arr.into_iter().next().unwrap() },
+      value => Query::In { field, value },
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::simplify::*;
+  use crate::query;
+
+  fn and(queries: Vec<Query>) -> Query {
+    chain(queries, true)
+  }
+
+  fn or(queries: Vec<Query>) -> Query {
+    chain(queries, false)
+  }
+
+  #[test]
+  fn simplify_corpus() {
+    let corpus = vec![
+      // None is dropped from AND and absorbs OR
+      (and(vec![Query::None, query!("a" == 1), Query::None]), query!("a" == 1)),
+      (or(vec![query!("a" == 1), Query::None]), Query::None),
+      // nested chains flatten and duplicates go
+      (Query::And(vec![query!("a" == 1 && "b" == 2), query!("b" == 2 && "c" == 3)]), query!("a" == 1 && "b" == 2 && "c" == 3)),
+      (or(vec![query!("a" > 1), query!("b" > 1), query!("a" > 1)]), query!("a" > 1 || "b" > 1)),
+      // contradictions and tautologies
+      (query!("a" == 1 && "a" == 2), falsity()),
+      (query!("a" == 1 && "a" != 1), falsity()),
+      (and(vec![query!("a" == 1), !query!("a" == 1)]), falsity()),
+      (or(vec![query!("a" == 1), !query!("a" == 1)]), Query::None),
+      (query!("a" > 5 && "a" < 3), falsity()),
+      (query!("a" > 5 && "a" < 5), falsity()),
+      (or(vec![falsity(), query!("a" == 1)]), query!("a" == 1)),
+      (and(vec![query!("b" == 1), falsity()]), falsity()),
+      (!!query!("a" == 1), query!("a" == 1)),
+      (Query::In { field: "a".to_owned(), value: Value::Array(Box::default()) }, falsity()),
+      // ranges
+      (query!("a" > 1 && "a" > 5), query!("a" > 5)),
+      (query!("a" >= 5 && "a" > 5), query!("a" > 5)),
+      (and(vec![query!("a" < 10), query!("a" <= 3)]), query!("a" <= 3)),
+      (query!("a" > 1 || "a" > 5), query!("a" > 1)),
+      (and(vec![query!("a" >= 5), query!("a" <= 5)]), query!("a" == 5)),
+      (query!("a" > 1 && "a" < 10 && "a" > 20), falsity()),
+      (query!("a" > 1 && "a" < 10 && "a" > 2.5f64), query!("a" > 2.5f64 && "a" < 10)),
+      // equality against ranges, IN and NOT EQUAL
+      (query!("a" == 3 && "a" > 1), query!("a" == 3)),
+      (query!("a" == 3 && "a" != 4), query!("a" == 3)),
+      (query!("a" in [1, 2] && "b" == 1), query!("a" in [1, 2] && "b" == 1)),
+      (Query::and(query!("a" in [1, 2, 3]), query!("a" in [2, 3, 4])), query!("a" in [2, 3])),
+      (Query::and(query!("a" in [1, 2]), query!("a" == 3)), falsity()),
+      (query!("a" == 5 || "a" > 1), query!("a" > 1)),
+      // equality chains become IN
+      (query!("a" == 1 || "a" == 2 || "a" == 3), query!("a" in [1, 2, 3])),
+      (query!("a" == 1 || "b" == 2 || "a" == 3), query!("a" in [1, 3] || "b" == 2)),
+      (Query::or(query!("a" in [1, 2]), query!("a" == 3)), query!("a" in [1, 2, 3])),
+      (query!("a" in [7]), query!("a" == 7)),
+      // null checks and incomparable values are left alone
+      (and(vec![Query::Eq { field: "a".to_owned(), value: Value::None }, query!("a" == 1)]), and(vec![Query::Eq { field: "a".to_owned(), value: Value::None }, query!("a" == 1)])),
+      (query!("a" == 1 && "a" > "x"), query!("a" == 1 && "a" > "x")),
+      // string order is up to the collation and big integers do not fit a float
+      (query!("a" > "B" && "a" > "a"), query!("a" > "B" && "a" > "a")),
+      (query!("a" >= "x" && "a" > "x"), query!("a" > "x")),
+      (query!("a" == "x" && "a" == "y"), falsity()),
+      (query!("a" == 9007199254740993i64 && "a" == 9007199254740992.0f64), query!("a" == 9007199254740993i64 && "a" == 9007199254740992.0f64)),
+      (query!("a" > 9007199254740993i64 && "a" > 1.5f64), query!("a" > 9007199254740993i64 && "a" > 1.5f64)),
+    ];
+    for (before, after) in corpus {
+      assert_eq!(before.clone().simplify(), after, "simplifying {:?}", before);
+    }
+  }
+}
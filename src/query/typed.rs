@@ -0,0 +1,151 @@
+use std::marker::PhantomData;
+
+use crate::query::*;
+
+// Implemented by #[derive(Queryable)]. `fields` returns the accessor struct
+// for the type with every path starting at `prefix`, which is how nested
+// structs end up as dotted paths.
+pub trait Queryable {
+  type Fields;
+  fn fields(prefix: &str) -> Self::Fields;
+}
+
+pub trait Numeric: Into<Value> {}
+
+impl Numeric for i8 {}
+impl Numeric for i16 {}
+impl Numeric for i32 {}
+impl Numeric for i64 {}
+impl Numeric for isize {}
+impl Numeric for u8 {}
+impl Numeric for u16 {}
+impl Numeric for u32 {}
+impl Numeric for f32 {}
+impl Numeric for f64 {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedField<T> {
+  name: String,
+  kind: PhantomData<T>,
+}
+
+impl<T> TypedField<T> {
+  pub fn new<S: Into<String>>(name: S) -> TypedField<T> {
+    TypedField { name: name.into(), kind: PhantomData }
+  }
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+  pub fn is_null(self) -> Query {
+    field(self.name).is_null()
+  }
+  pub fn is_not_null(self) -> Query {
+    field(self.name).is_not_null()
+  }
+}
+
+impl<T: Into<Value>> TypedField<T> {
+  pub fn eq<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).eq(value.into())
+  }
+  pub fn neq<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).neq(value.into())
+  }
+  pub fn is_in<V: Into<T>, I: IntoIterator<Item = V>>(self, values: I) -> Query {
+    field(self.name).is_in(values.into_iter().map(|v| v.into()))
+  }
+}
+
+impl<T: Numeric> TypedField<T> {
+  pub fn gt<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).gt(value.into())
+  }
+  pub fn gte<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).gte(value.into())
+  }
+  pub fn lt<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).lt(value.into())
+  }
+  pub fn lte<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).lte(value.into())
+  }
+}
+
+impl TypedField<String> {
+  pub fn rx<S: Into<String>>(self, pattern: S) -> Query {
+    field(self.name).rx(pattern.into())
+  }
+}
+
+impl<T: Into<Value>> TypedField<Vec<T>> {
+  pub fn contains<V: Into<T>>(self, value: V) -> Query {
+    field(self.name).contains(value.into())
+  }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod test {
+  use crate::query::typed::*;
+  use crate::Queryable;
+  use crate::query;
+
+  #[derive(Queryable)]
+  #[allow(dead_code)]
+  struct Account {
+    id: i64,
+    #[query(rename = "currency_iso")]
+    currency: String,
+    sequence: u32,
+    rate: f32,
+  }
+
+  #[derive(Queryable)]
+  #[allow(dead_code)]
+  struct Transfer {
+    #[query(rename = "_id")]
+    id: String,
+    amount: f64,
+    deleted: bool,
+    state: Option<String>,
+    tags: Vec<String>,
+    #[query(nested)]
+    source: Account,
+    #[query(nested, rename = "dest")]
+    destination: Account,
+    #[query(skip)]
+    cache: Vec<u8>,
+  }
+
+  #[test]
+  fn derived_fields() {
+    assert_eq!(Transfer::id().name(), "_id");
+    assert_eq!(Transfer::source().id().name(), "source.id");
+    assert_eq!(Transfer::destination().currency().name(), "dest.currency_iso");
+    assert_eq!(Transfer::amount().gt(21), query!("amount" > 21.0f64));
+    assert_eq!(Transfer::state().eq("Pending"), query!("state" == "Pending"));
+    assert_eq!(Transfer::state().is_null(), Query::Eq { field: "state".to_owned(), value: Value::None });
+    assert_eq!(Transfer::source().sequence().gte(7u32), query!("source.sequence" >= 7i64));
+    assert_eq!(Transfer::source().rate().lt(0.5f32), query!("source.rate" < 0.5f64));
+  }
+
+  #[test]
+  fn derived_fields_build_the_same_query() {
+    let q = Transfer::deleted().eq(false)
+      & (Transfer::source().id().eq(12) | Transfer::destination().id().eq(12))
+      & Transfer::tags().contains("za")
+      & Transfer::source().currency().is_in(vec!["USD", "EUR"]);
+    let q_r = Query::And(vec![
+      query!("deleted" == false),
+      query!("source.id" == 12i64 || "dest.id" == 12i64),
+      query!("tags" contains "za"),
+      Query::In { field: "source.currency_iso".to_owned(), value: vec!["USD", "EUR"].into() },
+    ]);
+    assert_eq!(q, q_r);
+    assert_eq!(Transfer::id().rx("^tr_"), query!("_id" %% "^tr_"));
+  }
+
+  #[test]
+  fn derived_fields_reject_misuse() {
+    trybuild::TestCases::new().compile_fail("tests/ui/typed_*.rs");
+  }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use crate::query::*;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Update {
+  Set { field: String, value: Value },
+  Unset { field: String },
+  Inc { field: String, value: Value },
+  Push { field: String, value: Value },
+  Pull { field: String, value: Value },
+  AddToSet { field: String, value: Value },
+}
+
+impl Update {
+  pub fn field(&self) -> &str {
+    match self {
+      Update::Set { field, .. } => field,
+      Update::Unset { field } => field,
+      Update::Inc { field, .. } => field,
+      Update::Push { field, .. } => field,
+      Update::Pull { field, .. } => field,
+      Update::AddToSet { field, .. } => field,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateError {
+  Conflict { field: String, other: String },
+  NestedField { field: String },
+}
+
+impl fmt::Display for UpdateError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      UpdateError::Conflict { field, other } => write!(f, "updates to {} and {} conflict", field, other),
+      UpdateError::NestedField { field } => write!(f, "{} is a nested path, which SET cannot assign", field),
+    }
+  }
+}
+
+impl std::error::Error for UpdateError {}
+
+fn overlaps(a: &str, b: &str) -> bool {
+  let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+  long.strip_prefix(short).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+// Postgres refuses two assignments to one column and Mongo refuses two
+// operators on the same path or on a path and its parent, so a list of
+// updates may touch each field at most once.
+pub fn check(updates: &[Update]) -> Result<(), UpdateError> {
+  for (i, u) in updates.iter().enumerate() {
+    if let Some(other) = updates[..i].iter().find(|other| overlaps(other.field(), u.field())) {
+      return Err(UpdateError::Conflict { field: other.field().to_owned(), other: u.field().to_owned() });
+    }
+  }
+  Ok(())
+}
+
+#[allow(unused_macros)]
+#[macro_export]
+macro_rules! update {
+  ( @op $key:tt = $value:expr ) => {{ Update::Set { field: $key.to_owned(), value: $value.into() } }};
+  ( @op $key:tt += $value:expr ) => {{ Update::Inc { field: $key.to_owned(), value: $value.into() } }};
+  ( @op $key:tt push $value:expr ) => {{ Update::Push { field: $key.to_owned(), value: $value.into() } }};
+  ( @op $key:tt pull $value:expr ) => {{ Update::Pull { field: $key.to_owned(), value: $value.into() } }};
+  ( @op $key:tt add_to_set $value:expr ) => {{ Update::AddToSet { field: $key.to_owned(), value: $value.into() } }};
+
+  ( @acc [$($acc:expr,)*] ) => {{ vec![$($acc),*] }};
+  ( @acc [$($acc:expr,)*] unset $key:tt $(, $($rest:tt)*)? ) => {{
+    update!(@acc [$($acc,)* Update::Unset { field: $key.to_owned() },] $($($rest)*)?)
+  }};
+  ( @acc [$($acc:expr,)*] $key:tt $op:tt $value:expr $(, $($rest:tt)*)? ) => {{
+    update!(@acc [$($acc,)* update!(@op $key $op $value),] $($($rest)*)?)
+  }};
+
+  ( $($rest:tt)+ ) => {{ update!(@acc [] $($rest)*) }};
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::*;
+  use crate::query::update::UpdateError;
+
+  #[test]
+  fn update_macro() {
+    let retries = 1;
+    let u = update!("state" = "Done", "retries" += retries, "tags" push "late", "tags" pull "new", "owners" add_to_set 7, unset "lock");
+    let u_r = vec![
+      Update::Set { field: "state".to_owned(), value: "Done".into() },
+      Update::Inc { field: "retries".to_owned(), value: 1.into() },
+      Update::Push { field: "tags".to_owned(), value: "late".into() },
+      Update::Pull { field: "tags".to_owned(), value: "new".into() },
+      Update::AddToSet { field: "owners".to_owned(), value: 7.into() },
+      Update::Unset { field: "lock".to_owned() },
+    ];
+    assert_eq!(u, u_r);
+    assert_eq!(update!("state" = { let s = "Do"; s.to_owned() + "ne" }), vec![Update::Set { field: "state".to_owned(), value: "Done".into() }]);
+  }
+
+  #[test]
+  fn update_conflicts() {
+    assert_eq!(update::check(&update!("a" = 1, "b" += 1, "ab" push 2, "a_b.c" = 3)), Ok(()));
+    let conflict = |field: &str, other: &str| Err(UpdateError::Conflict { field: field.to_owned(), other: other.to_owned() });
+    assert_eq!(update::check(&update!("tags" push "a", "tags" pull "b")), conflict("tags", "tags"));
+    assert_eq!(update::check(&update!("a.b" = 1, "a" = 2)), conflict("a.b", "a"));
+    assert_eq!(update::check(&update!("a" = 1, "a" = 2)), conflict("a", "a"));
+  }
+}
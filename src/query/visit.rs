@@ -0,0 +1,256 @@
+use crate::query::*;
+
+pub trait QueryVisitor {
+  fn visit(&mut self, query: &Query) {
+    walk(self, query)
+  }
+  fn visit_and(&mut self, queries: &[Query]) {
+    for query in queries {
+      self.visit(query);
+    }
+  }
+  fn visit_or(&mut self, queries: &[Query]) {
+    for query in queries {
+      self.visit(query);
+    }
+  }
+  fn visit_not(&mut self, query: &Query) {
+    self.visit(query);
+  }
+  fn visit_predicate(&mut self, _field: &str, _value: &Value) {}
+  fn visit_eq(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_neq(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_gt(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_gte(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_lt(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_lte(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_rx(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_in(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_contains(&mut self, field: &str, value: &Value) {
+    self.visit_predicate(field, value)
+  }
+  fn visit_none(&mut self) {}
+}
+
+pub fn walk<V: QueryVisitor + ?Sized>(visitor: &mut V, query: &Query) {
+  match query {
+    Query::And(queries) => visitor.visit_and(queries),
+    Query::Or(queries) => visitor.visit_or(queries),
+    Query::Not(query) => visitor.visit_not(query),
+    Query::Eq { field, value } => visitor.visit_eq(field, value),
+    Query::Neq { field, value } => visitor.visit_neq(field, value),
+    Query::Gt { field, value } => visitor.visit_gt(field, value),
+    Query::GtE { field, value } => visitor.visit_gte(field, value),
+    Query::Lt { field, value } => visitor.visit_lt(field, value),
+    Query::LtE { field, value } => visitor.visit_lte(field, value),
+    Query::Rx { field, value } => visitor.visit_rx(field, value),
+    Query::In { field, value } => visitor.visit_in(field, value),
+    Query::Contains { field, value } => visitor.visit_contains(field, value),
+    Query::None => visitor.visit_none(),
+  }
+}
+
+pub trait QueryFolder {
+  fn fold(&mut self, query: Query) -> Query {
+    fold(self, query)
+  }
+  fn fold_and(&mut self, queries: Vec<Query>) -> Query {
+    Query::And(queries)
+  }
+  fn fold_or(&mut self, queries: Vec<Query>) -> Query {
+    Query::Or(queries)
+  }
+  fn fold_not(&mut self, query: Query) -> Query {
+    Query::Not(Box::new(query))
+  }
+  fn fold_eq(&mut self, field: String, value: Value) -> Query {
+    Query::Eq { field, value }
+  }
+  fn fold_neq(&mut self, field: String, value: Value) -> Query {
+    Query::Neq { field, value }
+  }
+  fn fold_gt(&mut self, field: String, value: Value) -> Query {
+    Query::Gt { field, value }
+  }
+  fn fold_gte(&mut self, field: String, value: Value) -> Query {
+    Query::GtE { field, value }
+  }
+  fn fold_lt(&mut self, field: String, value: Value) -> Query {
+    Query::Lt { field, value }
+  }
+  fn fold_lte(&mut self, field: String, value: Value) -> Query {
+    Query::LtE { field, value }
+  }
+  fn fold_rx(&mut self, field: String, value: Value) -> Query {
+    Query::Rx { field, value }
+  }
+  fn fold_in(&mut self, field: String, value: Value) -> Query {
+    Query::In { field, value }
+  }
+  fn fold_contains(&mut self, field: String, value: Value) -> Query {
+    Query::Contains { field, value }
+  }
+  fn fold_none(&mut self) -> Query {
+    Query::None
+  }
+}
+
+// Children are folded before their parent, so fold_and and friends always
+// see already rewritten operands.
+pub fn fold<F: QueryFolder + ?Sized>(folder: &mut F, query: Query) -> Query {
+  match query {
+    Query::And(queries) => {
+      let queries = queries.into_iter().map(|q| folder.fold(q)).collect();
+      folder.fold_and(queries)
+    },
+    Query::Or(queries) => {
+      let queries = queries.into_iter().map(|q| folder.fold(q)).collect();
+      folder.fold_or(queries)
+    },
+    Query::Not(query) => {
+      let query = folder.fold(*query);
+      folder.fold_not(query)
+    },
+    Query::Eq { field, value } => folder.fold_eq(field, value),
+    Query::Neq { field, value } => folder.fold_neq(field, value),
+    Query::Gt { field, value } => folder.fold_gt(field, value),
+    Query::GtE { field, value } => folder.fold_gte(field, value),
+    Query::Lt { field, value } => folder.fold_lt(field, value),
+    Query::LtE { field, value } => folder.fold_lte(field, value),
+    Query::Rx { field, value } => folder.fold_rx(field, value),
+    Query::In { field, value } => folder.fold_in(field, value),
+    Query::Contains { field, value } => folder.fold_contains(field, value),
+    Query::None => folder.fold_none(),
+  }
+}
+
+struct Fields(Vec<String>);
+
+impl QueryVisitor for Fields {
+  fn visit_predicate(&mut self, field: &str, _value: &Value) {
+    if !self.0.iter().any(|f| f == field) {
+      self.0.push(field.to_owned());
+    }
+  }
+}
+
+impl Query {
+  pub fn predicate(&self) -> Option<(&String, &Value)> {
+    match self {
+      Query::Eq { field, value } | Query::Neq { field, value } | Query::Gt { field, value } | Query::GtE { field, value }
+        | Query::Lt { field, value } | Query::LtE { field, value } | Query::Rx { field, value } | Query::In { field, value }
+        | Query::Contains { field, value } => Some((field, value)),
+      _ => None,
+    }
+  }
+  pub fn predicate_mut(&mut self) -> Option<(&mut String, &mut Value)> {
+    match self {
+      Query::Eq { field, value } | Query::Neq { field, value } | Query::Gt { field, value } | Query::GtE { field, value }
+        | Query::Lt { field, value } | Query::LtE { field, value } | Query::Rx { field, value } | Query::In { field, value }
+        | Query::Contains { field, value } => Some((field, value)),
+      _ => None,
+    }
+  }
+  pub fn fields(&self) -> Vec<String> {
+    let mut fields = Fields(vec![]);
+    fields.visit(self);
+    fields.0
+  }
+  // Calls `f` on every node, parents before their children.
+  pub fn walk_mut<F: FnMut(&mut Query)>(&mut self, f: &mut F) {
+    f(self);
+    match self {
+      Query::And(queries) | Query::Or(queries) => {
+        for query in queries {
+          query.walk_mut(f);
+        }
+      },
+      Query::Not(query) => query.walk_mut(f),
+      _ => {},
+    }
+  }
+  pub fn map_fields<F: FnMut(&str) -> String>(mut self, mut f: F) -> Query {
+    self.walk_mut(&mut |query| if let Some((field, _)) = query.predicate_mut() {
+      *field = f(field);
+    });
+    self
+  }
+  pub fn map_values<F: FnMut(&str, Value) -> Value>(mut self, mut f: F) -> Query {
+    self.walk_mut(&mut |query| if let Some((field, value)) = query.predicate_mut() {
+      *value = f(field, std::mem::replace(value, Value::None));
+    });
+    self
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use crate::query::visit::*;
+  use crate::query;
+
+  struct Depth {
+    depth: usize,
+    max: usize,
+  }
+
+  impl QueryVisitor for Depth {
+    fn visit(&mut self, query: &Query) {
+      self.depth += 1;
+      self.max = self.max.max(self.depth);
+      walk(self, query);
+      self.depth -= 1;
+    }
+  }
+
+  // Rewrites every `!=` into `NOT ==` and leaves everything else alone.
+  struct NeqToNot;
+
+  impl QueryFolder for NeqToNot {
+    fn fold_neq(&mut self, field: String, value: Value) -> Query {
+      Query::Not(Box::new(Query::Eq { field, value }))
+    }
+  }
+
+  #[test]
+  fn visitor_fields_and_depth() {
+    let q = query!("b" > 2 || "a" < 0);
+    let q = Query::and(query!("a" == 1), Query::and(q, query!("c" contains "x")));
+    assert_eq!(q.fields(), vec!["a", "b", "c"]);
+    let mut depth = Depth { depth: 0, max: 0 };
+    depth.visit(&q);
+    assert_eq!(depth.max, 3);
+  }
+
+  #[test]
+  fn folder_rewrites_bottom_up() {
+    let q = query!("a" != 1 || "b" == 2);
+    let q_r = Query::or(Query::Not(Box::new(Query::Eq { field: "a".to_owned(), value: 1.into() })), Query::Eq { field: "b".to_owned(), value: 2.into() });
+    assert_eq!(NeqToNot.fold(q), q_r);
+  }
+
+  #[test]
+  fn map_fields_and_values() {
+    let q = query!("source.id" == 5 && "amount" > 10);
+    let q = q.map_fields(|field| field.replace('.', "_")).map_values(|field, value| match (field, value) {
+      ("amount", Value::Number(n)) => Value::Float(n as f64),
+      (_, value) => value,
+    });
+    assert_eq!(q, Query::and(Query::Eq { field: "source_id".to_owned(), value: 5.into() }, Query::Gt { field: "amount".to_owned(), value: 10.0f64.into() }));
+  }
+}
@@ -0,0 +1,186 @@
+use std::fmt::Display;
+
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::{Connection, ToSql};
+
+use crate::query::*;
+
+impl ToSql for Value {
+  fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+    match self {
+      Value::Uuid(u) => Ok(ToSqlOutput::from(u.hyphenated().to_string())),
+      Value::Number(n) => Ok(ToSqlOutput::from(*n)),
+      Value::Float(f) => Ok(ToSqlOutput::from(*f)),
+      Value::String(s) => Ok(ToSqlOutput::Borrowed(ValueRef::Text(s.as_bytes()))),
+      Value::Bool(b) => Ok(ToSqlOutput::from(*b)),
+      Value::None => Ok(ToSqlOutput::Borrowed(ValueRef::Null)),
+      Value::Array(_) => Err(rusqlite::Error::ToSqlConversionFailure("arrays cannot be bound as a single parameter".into())),
+    }
+  }
+}
+
+#[allow(dead_code)]
+pub struct Where {
+  where_clause: String,
+  params: Vec<Value>,
+}
+
+impl Display for Where {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.where_clause)
+  }
+}
+
+impl Where {
+  fn from(wc: String, params: Vec<Value>) -> Where {
+    Where { where_clause: wc, params }
+  }
+  pub fn params(&self) -> &[Value] {
+    &self.params
+  }
+}
+
+pub trait ToWhere {
+  fn to_where(&self) -> Where {
+    self.to_where_with_params(&mut vec![])
+  }
+  fn to_where_with_params(&self, params: &mut Vec<Value>) -> Where;
+}
+
+fn identifier(name: &str) -> String {
+  if name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+    name.to_owned()
+  } else {
+    format!("\"{}\"", name.replace('"', "\"\""))
+  }
+}
+
+// A quoted JSON path label ends at the first double quote, but its escapes
+// are decoded before keys are compared, so quotes are written as \u0022.
+fn label(segment: &str) -> String {
+  format!(".\"{}\"", segment.replace('\\', "\\\\").replace('"', "\\u0022"))
+}
+
+fn column(field: &str) -> String {
+  match field.split_once('.') {
+    Some((column, path)) => {
+      let path = path.split('.').map(label).collect::<String>();
+      format!("json_extract({}, '${}')", identifier(column), path.replace('\'', "''"))
+    },
+    None => identifier(field),
+  }
+}
+
+fn param(value: &Value, params: &mut Vec<Value>) -> String {
+  params.push(value.clone());
+  "?".to_owned()
+}
+
+fn compare(field: &str, op: &str, value: &Value, params: &mut Vec<Value>) -> Where {
+  Where::from(format!("{} {} {}", column(field), op, param(value, params)), params.clone())
+}
+
+fn join(queries: &[Query], op: &str, params: &mut Vec<Value>) -> Where {
+  let clauses = queries.iter().map(|q| q.to_where_with_params(params).to_string()).collect::<Vec<String>>();
+  Where::from(format!("({})", clauses.join(op)), params.clone())
+}
+
+impl ToWhere for Query {
+  fn to_where_with_params(&self, params: &mut Vec<Value>) -> Where {
+    match self {
+      Query::And(queries) if queries.is_empty() => Where::from("1".to_owned(), params.clone()),
+      Query::Or(queries) if queries.is_empty() => Where::from("0".to_owned(), params.clone()),
+      Query::And(queries) => join(queries, " AND ", params),
+      Query::Or(queries) => join(queries, " OR ", params),
+      Query::Not(query) => match query.as_ref() {
+        Query::None => Where::from("0".to_owned(), params.clone()),
+        Query::And(_) | Query::Or(_) => Where::from(format!("NOT {}", query.to_where_with_params(params)), params.clone()),
+        _ => Where::from(format!("NOT ({})", query.to_where_with_params(params)), params.clone()),
+      },
+      Query::Eq { field, value: Value::None } => Where::from(format!("{} IS NULL", column(field)), params.clone()),
+      Query::Neq { field, value: Value::None } => Where::from(format!("{} IS NOT NULL", column(field)), params.clone()),
+      Query::Eq { field, value } => compare(field, "=", value, params),
+      Query::Neq { field, value } => compare(field, "!=", value, params),
+      Query::Gt { field, value } => compare(field, ">", value, params),
+      Query::GtE { field, value } => compare(field, ">=", value, params),
+      Query::Lt { field, value } => compare(field, "<", value, params),
+      Query::LtE { field, value } => compare(field, "<=", value, params),
+      Query::Rx { field, value } => compare(field, "REGEXP", value, params),
+      Query::In { field, value: Value::Array(values) } => {
+        let placeholders = values.iter().map(|v| param(v, params)).collect::<Vec<String>>();
+        Where::from(format!("{} IN ({})", column(field), placeholders.join(", ")), params.clone())
+      },
+      Query::In { field, value } => Where::from(format!("{} IN ({})", column(field), param(value, params)), params.clone()),
+      Query::Contains { field, value } => Where::from(format!("EXISTS (SELECT 1 FROM json_each({}) WHERE value = {})", column(field), param(value, params)), params.clone()),
+      Query::None => Where::from("".to_owned(), params.clone()),
+    }
+  }
+}
+
+pub fn to_where(query: &dyn ToWhere) -> Where {
+  query.to_where()
+}
+
+pub fn register_regexp(conn: &Connection) -> rusqlite::Result<()> {
+  conn.create_scalar_function("regexp", 2, FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC, |ctx| {
+    let regex = ctx.get_or_create_aux(0, |pattern| -> Result<Regex, Box<dyn std::error::Error + Send + Sync + 'static>> {
+      Ok(Regex::new(pattern.as_str()?)?)
+    })?;
+    match ctx.get_raw(1) {
+      ValueRef::Text(text) => Ok(regex.is_match(std::str::from_utf8(text).map_err(|e| rusqlite::Error::UserFunctionError(e.into()))?)),
+      _ => Ok(false),
+    }
+  })
+}
+
+#[cfg(test)]
+mod test {
+  use rusqlite::{params_from_iter, Connection};
+  use crate::sqlite::{self, *};
+
+  #[test]
+  fn test_sqlite() {
+    let q = query!("b" in [1, 2] || "a" == 5);
+    let q = query!(..q; && "deleted" == false && "source.id" == 5);
+    let result = sqlite::to_where(&q);
+    assert_eq!(result.to_string(), r#"((b IN (?, ?) OR a = ?) AND deleted = ? AND json_extract(source, '$."id"') = ?)"#);
+    assert_eq!(result.params(), &[Value::from(1), Value::from(2), Value::from(5), Value::from(false), Value::from(5)]);
+    let q = query!("tags" contains "x" && "meta.audit.user" == "jo");
+    assert_eq!(sqlite::to_where(&q).to_string(), r#"(EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ?) AND json_extract(meta, '$."audit"."user"') = ?)"#);
+    let q = query!("created by" == 1 && "meta.it's \"q\"" == 2 && "x\" OR 1=1 --.a" == 3);
+    let w = r#"("created by" = ? AND json_extract(meta, '$."it''s \u0022q\u0022"') = ? AND json_extract("x"" OR 1=1 --", '$."a"') = ?)"#;
+    assert_eq!(sqlite::to_where(&q).to_string(), w);
+  }
+
+  fn ids(conn: &Connection, query: &Query) -> Vec<i64> {
+    let result = sqlite::to_where(query);
+    let mut stmt = conn.prepare(&format!("SELECT id FROM transfers WHERE {} ORDER BY id", result)).unwrap();
+    let rows = stmt.query_map(params_from_iter(result.params()), |row| row.get(0)).unwrap();
+    rows.map(Result::unwrap).collect()
+  }
+
+  #[test]
+  fn query_in_memory() {
+    let conn = Connection::open_in_memory().unwrap();
+    sqlite::register_regexp(&conn).unwrap();
+    conn.execute_batch("
+      CREATE TABLE transfers (id INTEGER PRIMARY KEY, state TEXT, amount REAL, deleted BOOLEAN, source TEXT, tags TEXT);
+      INSERT INTO transfers VALUES (1, 'Pending', 10.5, 0, '{\"id\": 12, \"currency_iso\": \"USD\"}', '[\"za\", \"us\"]');
+      INSERT INTO transfers VALUES (2, 'Done', 99.0, 0, '{\"id\": 13, \"currency_iso\": \"ZAR\"}', '[\"za\"]');
+      INSERT INTO transfers VALUES (3, 'Done', 5.0, 1, '{\"id\": 12, \"currency_iso\": \"USD\"}', '[]');
+      INSERT INTO transfers VALUES (4, NULL, 1.0, 0, '{\"id\": 14, \"it''s \\\"q\\\"\": 1}', '[\"uk\"]');
+    ").unwrap();
+    assert_eq!(ids(&conn, &query!("deleted" == false && "source.id" == 12)), vec![1]);
+    assert_eq!(ids(&conn, &query!("state" in ["Pending", "Done"] && "amount" > 6.0f64)), vec![1, 2]);
+    assert_eq!(ids(&conn, &query!("tags" contains "za")), vec![1, 2]);
+    assert_eq!(ids(&conn, &query!("source.currency_iso" %% "^US")), vec![1, 3]);
+    assert_eq!(ids(&conn, &Query::Eq { field: "state".to_owned(), value: Value::None }), vec![4]);
+    assert_eq!(ids(&conn, &query!("source.it's \"q\"" == 1)), vec![4]);
+    assert_eq!(ids(&conn, &Query::Not(Box::new(query!("deleted" == true || "amount" > 50.0f64)))), vec![1, 4]);
+    assert_eq!(ids(&conn, &query!("amount" > 50.0f64 && "amount" < 10.0f64).simplify()), Vec::<i64>::new());
+    assert_eq!(ids(&conn, &Query::And(vec![])), vec![1, 2, 3, 4]);
+    assert_eq!(ids(&conn, &Query::Or(vec![])), Vec::<i64>::new());
+  }
+}
@@ -0,0 +1,208 @@
+use std::fmt;
+use std::ops::Bound;
+
+use ::tantivy::query::{AllQuery, BooleanQuery, EmptyQuery, ExistsQuery, Occur, Query as TantivyQuery, RangeQuery, RegexQuery, TermQuery, TermSetQuery};
+use ::tantivy::schema::{Field, FieldType, IndexRecordOption, Schema};
+use ::tantivy::Term;
+
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  UnknownField { field: String },
+  NotIndexed { field: String },
+  Mismatch { field: String, value: Value },
+  Unsupported { operation: String, field: String },
+  Regex { field: String, pattern: String },
+  NotFast { field: String },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::UnknownField { field } => write!(f, "{} is not in the schema", field),
+      Error::NotIndexed { field } => write!(f, "{} is not indexed", field),
+      Error::Mismatch { field, value } => write!(f, "{:?} does not match the type of {}", value, field),
+      Error::Unsupported { operation, field } => write!(f, "{} is not supported on {}", operation, field),
+      Error::Regex { field, pattern } => write!(f, "invalid regex {:?} on {}", pattern, field),
+      Error::NotFast { field } => write!(f, "{} is not a fast field", field),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+fn field<'a>(schema: &'a Schema, name: &str) -> Result<(Field, &'a FieldType), Error> {
+  let field = schema.get_field(name).map_err(|_| Error::UnknownField { field: name.to_owned() })?;
+  let field_type = schema.get_field_entry(field).field_type();
+  if !field_type.is_indexed() {
+    return Err(Error::NotIndexed { field: name.to_owned() });
+  }
+  Ok((field, field_type))
+}
+
+// ExistsQuery reads the fast field columns and fails at search time when
+// there are none, so null checks are refused up front instead.
+fn exists(schema: &Schema, name: &str) -> Result<Box<dyn TantivyQuery>, Error> {
+  let (_, field_type) = field(schema, name)?;
+  if !field_type.is_fast() {
+    return Err(Error::NotFast { field: name.to_owned() });
+  }
+  Ok(Box::new(ExistsQuery::new(name.to_owned(), false)))
+}
+
+fn term(schema: &Schema, name: &str, value: &Value) -> Result<Term, Error> {
+  let (field, field_type) = field(schema, name)?;
+  match (field_type, value) {
+    (FieldType::Str(_), Value::String(s)) => Ok(Term::from_field_text(field, s)),
+    (FieldType::Str(_), Value::Uuid(u)) => Ok(Term::from_field_text(field, &u.hyphenated().to_string())),
+    (FieldType::I64(_), Value::Number(n)) => Ok(Term::from_field_i64(field, *n)),
+    (FieldType::U64(_), Value::Number(n)) if *n >= 0 => Ok(Term::from_field_u64(field, *n as u64)),
+    (FieldType::F64(_), Value::Number(n)) => Ok(Term::from_field_f64(field, *n as f64)),
+    (FieldType::F64(_), Value::Float(f)) => Ok(Term::from_field_f64(field, *f)),
+    (FieldType::Bool(_), Value::Bool(b)) => Ok(Term::from_field_bool(field, *b)),
+    _ => Err(Error::Mismatch { field: name.to_owned(), value: value.clone() }),
+  }
+}
+
+fn term_query(schema: &Schema, name: &str, value: &Value) -> Result<Box<dyn TantivyQuery>, Error> {
+  Ok(Box::new(TermQuery::new(term(schema, name, value)?, IndexRecordOption::Basic)))
+}
+
+fn range(lower: Bound<Term>, upper: Bound<Term>) -> Box<dyn TantivyQuery> {
+  Box::new(RangeQuery::new(lower, upper))
+}
+
+// A MustNot clause on its own matches nothing, so negations are taken out of
+// the set of all documents.
+fn must_not(query: Box<dyn TantivyQuery>) -> Box<dyn TantivyQuery> {
+  Box::new(BooleanQuery::new(vec![(Occur::Must, Box::new(AllQuery)), (Occur::MustNot, query)]))
+}
+
+fn regex(schema: &Schema, name: &str, value: &Value) -> Result<Box<dyn TantivyQuery>, Error> {
+  let (field, field_type) = field(schema, name)?;
+  let pattern = match (field_type, value) {
+    (FieldType::Str(_), Value::String(s)) => s,
+    (FieldType::Str(_), _) => return Err(Error::Mismatch { field: name.to_owned(), value: value.clone() }),
+    _ => return Err(Error::Unsupported { operation: "regex".to_owned(), field: name.to_owned() }),
+  };
+  let query = RegexQuery::from_pattern(&anchored(pattern), field).map_err(|_| Error::Regex { field: name.to_owned(), pattern: pattern.clone() })?;
+  Ok(Box::new(query))
+}
+
+fn boolean(query: &Query, and: bool, schema: &Schema) -> Result<Box<dyn TantivyQuery>, Error> {
+  let occur = if and { Occur::Must } else { Occur::Should };
+  let mut queries = vec![];
+  flatten(query, and, &mut queries);
+  let clauses = queries.into_iter().map(|q| Ok((occur, q.to_tantivy(schema)?))).collect::<Result<Vec<_>, Error>>()?;
+  Ok(Box::new(BooleanQuery::new(clauses)))
+}
+
+pub trait ToTantivy {
+  fn to_tantivy(&self, schema: &Schema) -> Result<Box<dyn TantivyQuery>, Error>;
+}
+
+impl ToTantivy for Query {
+  fn to_tantivy(&self, schema: &Schema) -> Result<Box<dyn TantivyQuery>, Error> {
+    match self {
+      Query::And(queries) if queries.is_empty() => Query::None.to_tantivy(schema),
+      Query::Or(queries) if queries.is_empty() => Query::Not(Box::new(Query::None)).to_tantivy(schema),
+      Query::And(_) => boolean(self, true, schema),
+      Query::Or(_) => boolean(self, false, schema),
+      Query::Not(query) if **query == Query::None => Ok(Box::new(EmptyQuery)),
+      Query::Not(query) => Ok(must_not(query.to_tantivy(schema)?)),
+      Query::Eq { field, value: Value::None } => Ok(must_not(exists(schema, field)?)),
+      Query::Neq { field, value: Value::None } => exists(schema, field),
+      Query::Eq { field, value } => term_query(schema, field, value),
+      Query::Neq { field, value } => Ok(must_not(term_query(schema, field, value)?)),
+      Query::Gt { field, value } => Ok(range(Bound::Excluded(term(schema, field, value)?), Bound::Unbounded)),
+      Query::GtE { field, value } => Ok(range(Bound::Included(term(schema, field, value)?), Bound::Unbounded)),
+      Query::Lt { field, value } => Ok(range(Bound::Unbounded, Bound::Excluded(term(schema, field, value)?))),
+      Query::LtE { field, value } => Ok(range(Bound::Unbounded, Bound::Included(term(schema, field, value)?))),
+      Query::Rx { field, value } => regex(schema, field, value),
+      Query::In { field, value: Value::Array(values) } => {
+        let terms = values.iter().map(|v| term(schema, field, v)).collect::<Result<Vec<Term>, Error>>()?;
+        Ok(Box::new(TermSetQuery::new(terms)))
+      },
+      Query::In { field, value } => term_query(schema, field, value),
+      Query::Contains { field, value } => term_query(schema, field, value),
+      Query::None => Ok(Box::new(AllQuery)),
+    }
+  }
+}
+
+pub fn to_tantivy(query: &dyn ToTantivy, schema: &Schema) -> Result<Box<dyn TantivyQuery>, Error> {
+  query.to_tantivy(schema)
+}
+
+#[cfg(test)]
+mod test {
+  use ::tantivy::collector::DocSetCollector;
+  use ::tantivy::schema::{Schema, FAST, INDEXED, STORED, STRING, TEXT};
+  use ::tantivy::{doc, Index, TantivyDocument};
+  use ::tantivy::schema::Value as _;
+  use crate::tantivy::{self, *};
+
+  fn index() -> (Schema, Index) {
+    let mut builder = Schema::builder();
+    let id = builder.add_i64_field("id", INDEXED | STORED | FAST);
+    let state = builder.add_text_field("state", STRING | FAST);
+    let amount = builder.add_f64_field("amount", INDEXED | FAST);
+    let deleted = builder.add_bool_field("deleted", INDEXED);
+    let tags = builder.add_text_field("tags", STRING);
+    let note = builder.add_text_field("note", TEXT);
+    builder.add_text_field("raw", STORED);
+    let schema = builder.build();
+    let index = Index::create_in_ram(schema.clone());
+    let mut writer = index.writer_with_num_threads(1, 15_000_000).unwrap();
+    writer.add_document(doc!(id => 1i64, state => "Pending", amount => 10.5f64, deleted => false, tags => "za", tags => "us", note => "first")).unwrap();
+    writer.add_document(doc!(id => 2i64, state => "Done", amount => 99.0f64, deleted => false, tags => "za", note => "second")).unwrap();
+    writer.add_document(doc!(id => 3i64, state => "Done", amount => 5.0f64, deleted => true, note => "third")).unwrap();
+    writer.add_document(doc!(id => 4i64, amount => 1.0f64, deleted => false, tags => "uk", note => "fourth")).unwrap();
+    writer.commit().unwrap();
+    (schema, index)
+  }
+
+  fn ids(schema: &Schema, index: &Index, query: &Query) -> Vec<i64> {
+    let searcher = index.reader().unwrap().searcher();
+    let id = schema.get_field("id").unwrap();
+    let docs = searcher.search(tantivy::to_tantivy(query, schema).unwrap().as_ref(), &DocSetCollector).unwrap();
+    let mut ids = docs.into_iter().map(|address| {
+      let doc: TantivyDocument = searcher.doc(address).unwrap();
+      doc.get_first(id).and_then(|v| v.as_i64()).unwrap()
+    }).collect::<Vec<i64>>();
+    ids.sort();
+    ids
+  }
+
+  #[test]
+  fn query_ram_index() {
+    let (schema, index) = index();
+    assert_eq!(ids(&schema, &index, &query!("deleted" == false && "amount" > 5.0f64)), vec![1, 2]);
+    let q = Query::and(query!("state" in ["Pending", "Done"]), Query::LtE { field: "amount".to_owned(), value: 10.5f64.into() });
+    assert_eq!(ids(&schema, &index, &q), vec![1, 3]);
+    assert_eq!(ids(&schema, &index, &query!("tags" contains "za" || "id" == 4)), vec![1, 2, 4]);
+    assert_eq!(ids(&schema, &index, &query!("state" %% "^Pen")), vec![1]);
+    assert_eq!(ids(&schema, &index, &query!("state" %% "on")), vec![2, 3]);
+    assert_eq!(ids(&schema, &index, &query!("state" %% "^Pen|one$")), vec![1, 2, 3]);
+    assert_eq!(ids(&schema, &index, &query!("state" != "Done")), vec![1, 4]);
+    assert_eq!(ids(&schema, &index, &Query::Eq { field: "state".to_owned(), value: Value::None }), vec![4]);
+    assert_eq!(ids(&schema, &index, &Query::Not(Box::new(query!("deleted" == true || "amount" > 50.0f64)))), vec![1, 4]);
+    assert_eq!(ids(&schema, &index, &Query::None), vec![1, 2, 3, 4]);
+    assert_eq!(ids(&schema, &index, &Query::Not(Box::new(Query::None))), Vec::<i64>::new());
+  }
+
+  #[test]
+  fn query_schema_errors() {
+    let (schema, _) = index();
+    assert_eq!(tantivy::to_tantivy(&query!("missing" == 1), &schema).err(), Some(tantivy::Error::UnknownField { field: "missing".to_owned() }));
+    assert_eq!(tantivy::to_tantivy(&query!("raw" == "x"), &schema).err(), Some(tantivy::Error::NotIndexed { field: "raw".to_owned() }));
+    assert_eq!(tantivy::to_tantivy(&query!("id" == "x"), &schema).err(), Some(tantivy::Error::Mismatch { field: "id".to_owned(), value: "x".into() }));
+    assert_eq!(tantivy::to_tantivy(&query!("id" %% "^1"), &schema).err(), Some(tantivy::Error::Unsupported { operation: "regex".to_owned(), field: "id".to_owned() }));
+    assert_eq!(tantivy::to_tantivy(&query!("state" %% "("), &schema).err(), Some(tantivy::Error::Regex { field: "state".to_owned(), pattern: "(".to_owned() }));
+    let q = Query::Eq { field: "tags".to_owned(), value: Value::None };
+    assert_eq!(tantivy::to_tantivy(&q, &schema).err(), Some(tantivy::Error::NotFast { field: "tags".to_owned() }));
+    let q = Query::Neq { field: "deleted".to_owned(), value: Value::None };
+    assert_eq!(tantivy::to_tantivy(&q, &schema).err(), Some(tantivy::Error::NotFast { field: "deleted".to_owned() }));
+  }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+
+use crate::query::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+  Unsupported { operation: String, field: String },
+  UnsupportedValue { field: String, value: Value },
+  InvalidField { field: String },
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Unsupported { operation, field } => write!(f, "{} is not supported on {}", operation, field),
+      Error::UnsupportedValue { field, value } => write!(f, "{:?} cannot be compared with {}", value, field),
+      Error::InvalidField { field } => write!(f, "{:?} cannot be used as a field name in filter_by", field),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+// Typesense has no escape sequence inside backtick quoted values, so a value
+// containing a backtick cannot be expressed at all.
+fn literal(field: &str, value: &Value) -> Result<String, Error> {
+  match value {
+    Value::Uuid(u) => Ok(format!("`{}`", u.hyphenated())),
+    Value::Number(n) => Ok(n.to_string()),
+    Value::Float(f) => Ok(f.to_string()),
+    Value::String(s) if !s.contains('`') => Ok(format!("`{}`", s)),
+    Value::Bool(b) => Ok(b.to_string()),
+    _ => Err(Error::UnsupportedValue { field: field.to_owned(), value: value.clone() }),
+  }
+}
+
+// filter_by has no way to quote a field name either, so names are limited to
+// dotted identifiers rather than passed through.
+fn name(field: &str) -> Result<&str, Error> {
+  match field.split('.').all(|s| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')) {
+    true => Ok(field),
+    false => Err(Error::InvalidField { field: field.to_owned() }),
+  }
+}
+
+fn compare(field: &str, op: &str, value: &Value) -> Result<String, Error> {
+  Ok(format!("{}:{}{}", name(field)?, op, literal(field, value)?))
+}
+
+fn list(field: &str, op: &str, value: &Value) -> Result<String, Error> {
+  let values = match value {
+    Value::Array(values) => values.iter().map(|v| literal(field, v)).collect::<Result<Vec<String>, Error>>()?,
+    _ => vec![literal(field, value)?],
+  };
+  Ok(format!("{}:{}[{}]", name(field)?, op, values.join(", ")))
+}
+
+fn group(query: &Query) -> Result<String, Error> {
+  match query {
+    Query::And(_) | Query::Or(_) => Ok(format!("({})", query.to_filter()?)),
+    _ => query.to_filter(),
+  }
+}
+
+fn join(query: &Query, and: bool) -> Result<String, Error> {
+  let mut queries = vec![];
+  flatten(query, and, &mut queries);
+  let filters = queries.into_iter().map(group).collect::<Result<Vec<String>, Error>>()?;
+  Ok(filters.join(if and { " && " } else { " || " }))
+}
+
+pub trait ToFilter {
+  fn to_filter(&self) -> Result<String, Error>;
+}
+
+impl ToFilter for Query {
+  fn to_filter(&self) -> Result<String, Error> {
+    match self {
+      Query::And(queries) if queries.is_empty() => Query::None.to_filter(),
+      Query::Or(queries) if queries.is_empty() => Query::Not(Box::new(Query::None)).to_filter(),
+      Query::And(_) => join(self, true),
+      Query::Or(_) => join(self, false),
+      // filter_by has no general negation, so NOT is pushed down to the
+      // predicates and negated comparisons are written as their duals.
+      Query::Not(query) => match query.as_ref() {
+        Query::In { field, value } => list(field, "!=", value),
+        Query::Contains { field, value } => compare(field, "!=", value),
+        Query::Gt { field, value } => compare(field, "<=", value),
+        Query::GtE { field, value } => compare(field, "<", value),
+        Query::Lt { field, value } => compare(field, ">=", value),
+        Query::LtE { field, value } => compare(field, ">", value),
+        Query::Rx { field, .. } => Err(Error::Unsupported { operation: "regex".to_owned(), field: field.clone() }),
+        // filter_by has no boolean literals to write false with.
+        Query::None => Err(Error::Unsupported { operation: "FALSE".to_owned(), field: "".to_owned() }),
+        _ => self.clone().to_nnf().to_filter(),
+      },
+      Query::Eq { field, value: Value::None } | Query::Neq { field, value: Value::None } => {
+        Err(Error::Unsupported { operation: "null comparison".to_owned(), field: field.clone() })
+      },
+      Query::Eq { field, value } => compare(field, "=", value),
+      Query::Neq { field, value } => compare(field, "!=", value),
+      Query::Gt { field, value } => compare(field, ">", value),
+      Query::GtE { field, value } => compare(field, ">=", value),
+      Query::Lt { field, value } => compare(field, "<", value),
+      Query::LtE { field, value } => compare(field, "<=", value),
+      Query::Rx { field, .. } => Err(Error::Unsupported { operation: "regex".to_owned(), field: field.clone() }),
+      Query::In { field, value } => list(field, "=", value),
+      Query::Contains { field, value } => compare(field, "=", value),
+      Query::None => Ok("".to_owned()),
+    }
+  }
+}
+
+pub fn to_filter(query: &dyn ToFilter) -> Result<String, Error> {
+  query.to_filter()
+}
+
+#[cfg(test)]
+mod test {
+  use crate::typesense::{self, *};
+
+  #[test]
+  fn test_filter() {
+    let q = Query::and(query!("a" == 1), query!("b" > 2 || "c" in ["x", "y"]));
+    assert_eq!(typesense::to_filter(&q).unwrap(), "a:=1 && (b:>2 || c:=[`x`, `y`])");
+    let q = query!("deleted" == false && "source.id" == 5 && "tags" contains "za");
+    assert_eq!(typesense::to_filter(&q).unwrap(), "deleted:=false && source.id:=5 && tags:=`za`");
+  }
+
+  #[test]
+  fn query_negation() {
+    let q = Query::Not(Box::new(query!("state" == "Done" || "amount" >= 2.5f64)));
+    assert_eq!(typesense::to_filter(&q).unwrap(), "state:!=`Done` && amount:<2.5");
+    let q = Query::Not(Box::new(query!("state" in ["Done", "Failed"] && "tags" contains "test")));
+    assert_eq!(typesense::to_filter(&q).unwrap(), "state:!=[`Done`, `Failed`] || tags:!=`test`");
+    let q = Query::Not(Box::new(Query::and(!query!("a" < 1), Query::Not(Box::new(query!("b" == 2 || "c" <= 3))))));
+    assert_eq!(typesense::to_filter(&q).unwrap(), "a:<1 || b:=2 || c:<=3");
+    assert_eq!(typesense::to_filter(&Query::Not(Box::new(Query::Not(Box::new(Query::None))))).unwrap(), "");
+  }
+
+  #[test]
+  fn query_escaping_and_errors() {
+    assert_eq!(typesense::to_filter(&query!("name" == "a, b && (c)")).unwrap(), "name:=`a, b && (c)`");
+    let q = query!("name" == "back`tick");
+    assert_eq!(typesense::to_filter(&q), Err(typesense::Error::UnsupportedValue { field: "name".to_owned(), value: "back`tick".into() }));
+    let q = query!("name" %% "^jo");
+    assert_eq!(typesense::to_filter(&q), Err(typesense::Error::Unsupported { operation: "regex".to_owned(), field: "name".to_owned() }));
+    assert_eq!(typesense::to_filter(&Query::Not(Box::new(Query::None))), Err(typesense::Error::Unsupported { operation: "FALSE".to_owned(), field: "".to_owned() }));
+    let invalid = |field: &str| Err(typesense::Error::InvalidField { field: field.to_owned() });
+    assert_eq!(typesense::to_filter(&query!("a:=1 || b" == 2)), invalid("a:=1 || b"));
+    assert_eq!(typesense::to_filter(&query!("created by" in [1, 2])), invalid("created by"));
+    assert_eq!(typesense::to_filter(&!query!("a." > 1)), invalid("a."));
+    assert_eq!(typesense::to_filter(&query!("ship-to.zip" == 1)).unwrap(), "ship-to.zip:=1");
+  }
+}
@@ -0,0 +1,5 @@
+use querylib::q;
+
+fn main() {
+  let _ = q!("deleted == false && age >> 21");
+}
@@ -0,0 +1,10 @@
+use querylib::Queryable;
+
+#[derive(Queryable)]
+struct User {
+  name: String,
+}
+
+fn main() {
+  let _ = User::name().gt("jo");
+}
@@ -0,0 +1,13 @@
+use querylib::Queryable;
+
+#[derive(Queryable)]
+struct Counter {
+  hits: u64,
+}
+
+#[derive(Queryable)]
+struct Buffer {
+  len: usize,
+}
+
+fn main() {}